@@ -1,63 +1,813 @@
-use std::fmt;
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
 
-use clap::{Parser, ValueEnum};
+use chrono::{Datelike, NaiveDate, TimeDelta, Weekday};
+use chrono_tz::Tz;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use snapgenda::{FreeSlotQuery, WeekDay, WeekRequest, WeekStart};
+
+use crate::{
+    http::RetryPolicy,
+    render::{HourRange, Privacy},
+    theme::ThemeName,
+};
 
 #[derive(Debug)]
 pub enum Error {
-    InvalidGoogleArgs(String),
+    GoogleArgs(String),
+    IcsArgs(String),
+    Week(String),
+    TimeZone(String),
+    MissingProvider,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::InvalidGoogleArgs(e) => {
+            Error::GoogleArgs(e) => {
                 write!(f, "Invalid Google Args: {}", e)
             }
+            Error::IcsArgs(e) => {
+                write!(f, "Invalid ICS Args: {}", e)
+            }
+            Error::Week(e) => {
+                write!(f, "Invalid week: {}", e)
+            }
+            Error::TimeZone(e) => {
+                write!(f, "Invalid time zone: {}", e)
+            }
+            Error::MissingProvider => {
+                write!(f, "Missing --provider")
+            }
         }
     }
 }
 
+impl From<snapgenda::Error> for Error {
+    fn from(error: snapgenda::Error) -> Self {
+        Error::Week(error.to_string())
+    }
+}
+
 impl std::error::Error for Error {}
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum Provider {
     GoogleCalendar,
+    /// Local iCalendar files, see --ics-file.
+    Ics,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Default)]
+pub enum FirstWeekday {
+    /// ISO 8601 weeks.
+    #[default]
+    Monday,
+    /// US weeks, week 1 is the week containing January 1st.
+    Sunday,
+}
+
+impl From<FirstWeekday> for WeekStart {
+    fn from(first_weekday: FirstWeekday) -> Self {
+        match first_weekday {
+            FirstWeekday::Monday => WeekStart::Monday,
+            FirstWeekday::Sunday => WeekStart::Sunday,
+        }
+    }
+}
+
+/// How several calendars are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Default)]
+pub enum View {
+    /// One column per day, busy if anyone is busy.
+    #[default]
+    Merged,
+    /// One column per calendar under every day.
+    SideBySide,
+}
+
+/// Minutes per rendered row.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Default)]
+pub enum Granularity {
+    #[default]
+    #[value(name = "60")]
+    Sixty,
+    #[value(name = "30")]
+    Thirty,
+    #[value(name = "15")]
+    Fifteen,
+}
+
+impl Granularity {
+    pub fn minutes(self) -> u32 {
+        match self {
+            Granularity::Sixty => 60,
+            Granularity::Thirty => 30,
+            Granularity::Fifteen => 15,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
 #[command(author = "Torwalt", version = "0.1", about = "Snapgenda", long_about = None)]
+#[command(group(ArgGroup::new("week_selection").args(["week", "date", "this_week", "next_week"])))]
+#[command(subcommand_negates_reqs = true)]
 pub struct Args {
-    #[arg(long)]
-    pub calendar_email: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    #[arg(value_enum, long)]
-    pub provider: Provider,
+    /// Google calendar to read, can be given multiple times or as a comma separated list.
+    #[arg(long, value_delimiter = ',')]
+    pub calendar_email: Vec<String>,
+
+    #[arg(value_enum, long, required = true)]
+    pub provider: Option<Provider>,
 
     #[arg(long)]
     pub google_id: Option<String>,
 
     #[arg(long)]
     pub google_secret: Option<String>,
+
+    /// JSON key of a Google service account to sign in with instead of --google-id and
+    /// --google-secret, for runs without a browser such as cron jobs.
+    #[arg(long, conflicts_with_all = ["google_id", "google_secret", "manual_auth"])]
+    pub google_service_account_key: Option<PathBuf>,
+
+    /// User whose calendars the service account reads. Needs domain-wide delegation of the
+    /// calendar.readonly scope in the Google Workspace admin console.
+    #[arg(long, requires = "google_service_account_key")]
+    pub google_impersonate: Option<String>,
+
+    /// Only read when Google calendars are busy, for calendars shared with free/busy
+    /// permission. Used automatically when reading the events is forbidden.
+    #[arg(long)]
+    pub free_busy: bool,
+
+    /// Retries of Google requests failing with a timeout, 429 or a server error. 0 disables
+    /// retrying.
+    #[arg(long, default_value_t = 3)]
+    pub max_retries: u32,
+
+    /// Seconds a single Google request may take.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: u64,
+
+    /// iCalendar file to read events from, can be given multiple times.
+    #[arg(long)]
+    pub ics_file: Vec<PathBuf>,
+
+    /// Paste the redirect URL after authorizing instead of receiving it on a local port.
+    /// Use this when the browser runs on another machine.
+    #[arg(long)]
+    pub manual_auth: bool,
+
+    /// Week number to render. Defaults to the current week.
+    #[arg(long)]
+    pub week: Option<u8>,
+
+    /// Year of --week. Defaults to the current year.
+    #[arg(
+        long,
+        requires = "week",
+        value_parser = clap::value_parser!(i32).range(snapgenda::MIN_YEAR as i64..=snapgenda::MAX_YEAR as i64)
+    )]
+    pub year: Option<i32>,
+
+    /// Render the week containing this date (YYYY-MM-DD).
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+
+    /// Render the current week.
+    #[arg(long)]
+    pub this_week: bool,
+
+    /// Render the week after the current one.
+    #[arg(long)]
+    pub next_week: bool,
+
+    /// Move the selected week by this many weeks, e.g. -2.
+    #[arg(long, allow_negative_numbers = true)]
+    pub offset: Option<i64>,
+
+    #[arg(value_enum, long, default_value_t)]
+    pub first_weekday: FirstWeekday,
+
+    /// IANA time zone to render the week in, e.g. Europe/Berlin. Defaults to the system time
+    /// zone.
+    #[arg(long)]
+    pub tz: Option<Tz>,
+
+    /// Hours of the day to render, e.g. 08-19 for 08:00 until 19:00.
+    #[arg(long, default_value = "00-24")]
+    pub hours: HourRange,
+
+    /// Minutes per rendered row.
+    #[arg(value_enum, long, default_value_t)]
+    pub granularity: Granularity,
+
+    /// How the matrix is drawn. Defaults to the theme of the config file, then ascii. ansi
+    /// falls back to unicode when stdout is not a terminal or NO_COLOR is set.
+    #[arg(value_enum, long)]
+    pub theme: Option<ThemeName>,
+
+    /// What the matrix shows of events. Private events never show their title.
+    #[arg(value_enum, long, default_value_t)]
+    pub privacy: Privacy,
+
+    /// How multiple calendars or ICS files are rendered.
+    #[arg(value_enum, long, default_value_t)]
+    pub view: View,
+
+    /// Config file to use instead of snapgenda/config.toml in the config directory.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+pub struct IcsArgs {
+    pub files: Vec<PathBuf>,
+}
+
+impl IcsArgs {
+    pub fn new(args: Args) -> Result<IcsArgs, Error> {
+        if args.ics_file.is_empty() {
+            return Err(Error::IcsArgs("missing ics_file".to_string()));
+        }
+
+        Ok(IcsArgs {
+            files: args.ics_file,
+        })
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage the cached Google credentials.
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+    /// List the free slots all calendars have in common instead of rendering the week.
+    Free(FreeArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct FreeArgs {
+    /// Shortest free slot to list, in minutes.
+    #[arg(long, default_value_t = 30)]
+    pub min_duration: u32,
+
+    /// Minutes to keep free before and after meetings.
+    #[arg(long, default_value_t = 0)]
+    pub buffer: u32,
+
+    /// Hours of the day to search, e.g. 09-17 for 09:00 until 17:00.
+    #[arg(long, default_value = "09-17")]
+    pub working_hours: HourRange,
+
+    /// Days to leave out, e.g. sat,sun or none.
+    #[arg(long, default_value = "sat,sun")]
+    pub exclude_days: WeekDays,
+}
+
+impl FreeArgs {
+    pub fn query(&self) -> FreeSlotQuery {
+        FreeSlotQuery {
+            working_hours: self.working_hours.times(),
+            min_duration: TimeDelta::minutes(self.min_duration as i64),
+            buffer: TimeDelta::minutes(self.buffer as i64),
+            excluded_days: self.exclude_days.0.clone(),
+        }
+    }
+}
+
+/// Comma separated week days like mon,tue or none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeekDays(pub Vec<WeekDay>);
+
+impl FromStr for WeekDays {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "none" {
+            return Ok(WeekDays(Vec::new()));
+        }
+
+        s.split(',')
+            .map(|day| {
+                day.trim()
+                    .parse::<Weekday>()
+                    .map(WeekDay::from)
+                    .map_err(|_| format!("{} is not a week day like mon", day))
+            })
+            .collect::<Result<Vec<WeekDay>, String>>()
+            .map(WeekDays)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuthAction {
+    /// Show whether tokens are cached and when they expire.
+    Status,
+    /// Remove the cached tokens.
+    Logout,
+}
+
+impl Args {
+    /// Returns --provider, which clap only enforces without a subcommand.
+    pub fn provider(&self) -> Result<Provider, Error> {
+        self.provider.clone().ok_or(Error::MissingProvider)
+    }
+
+    pub fn row_minutes(&self) -> u32 {
+        self.granularity.minutes()
+    }
+
+    /// Resolves the week selection flags to a single week. Relative selections are based on
+    /// today.
+    pub fn week_request(&self, today: NaiveDate) -> Result<WeekRequest, Error> {
+        let week_start = WeekStart::from(self.first_weekday);
+        let current = WeekRequest::containing_with_start(today, week_start);
+
+        let wr = if let Some(week) = self.week {
+            let year = self.year.unwrap_or(current.year());
+            WeekRequest::new_with_start(week, year, week_start)?
+        } else if let Some(date) = self.date {
+            if !(snapgenda::MIN_YEAR..=snapgenda::MAX_YEAR).contains(&date.year()) {
+                return Err(Error::Week(format!("date {} is out of range", date)));
+            }
+            WeekRequest::containing_with_start(date, week_start)
+        } else if self.next_week {
+            current.offset(1)?
+        } else {
+            current
+        };
+
+        Ok(wr.offset(self.offset.unwrap_or(0))?)
+    }
+
+    /// Returns the time zone given by --tz or the one of the system. Systems without a
+    /// configured time zone use UTC.
+    pub fn time_zone(&self) -> Result<Tz, Error> {
+        if let Some(tz) = self.tz {
+            return Ok(tz);
+        }
+
+        let Ok(name) = iana_time_zone::get_timezone() else {
+            return Ok(Tz::UTC);
+        };
+
+        name.parse().map_err(|_| {
+            Error::TimeZone(format!(
+                "unknown system time zone {}, set one with --tz",
+                name
+            ))
+        })
+    }
+}
+
+pub enum GoogleAuth {
+    /// OAuth client, the user signs in with the browser.
+    Client {
+        id: String,
+        secret: String,
+        manual_auth: bool,
+    },
+    ServiceAccount {
+        key: PathBuf,
+        impersonate: Option<String>,
+    },
 }
 
 pub struct GoogleArgs {
-    pub calendar_email: String,
-    pub provider: Provider,
-    pub google_id: String,
-    pub google_secret: String,
+    pub calendar_emails: Vec<String>,
+    pub auth: GoogleAuth,
+    pub free_busy: bool,
+    pub retry_policy: RetryPolicy,
 }
 
 impl GoogleArgs {
     pub fn new(args: Args) -> Result<GoogleArgs, Error> {
         Ok(GoogleArgs {
-            calendar_email: args.calendar_email,
-            provider: Provider::GoogleCalendar,
-            google_id: args
-                .google_id
-                .ok_or(Error::InvalidGoogleArgs("missing google_id".to_string()))?,
-            google_secret: args.google_secret.ok_or(Error::InvalidGoogleArgs(
-                "missing google_secret".to_string(),
-            ))?,
+            calendar_emails: match args.calendar_email {
+                emails if emails.is_empty() => {
+                    return Err(Error::GoogleArgs("missing calendar_email".to_string()))
+                }
+                emails => emails,
+            },
+            auth: match args.google_service_account_key {
+                Some(key) => GoogleAuth::ServiceAccount {
+                    key,
+                    impersonate: args.google_impersonate,
+                },
+                None => GoogleAuth::Client {
+                    id: args
+                        .google_id
+                        .ok_or(Error::GoogleArgs("missing google_id".to_string()))?,
+                    secret: args
+                        .google_secret
+                        .ok_or(Error::GoogleArgs("missing google_secret".to_string()))?,
+                    manual_auth: args.manual_auth,
+                },
+            },
+            free_busy: args.free_busy,
+            retry_policy: RetryPolicy {
+                max_retries: args.max_retries,
+                timeout: Duration::from_secs(args.timeout),
+                ..RetryPolicy::default()
+            },
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct WeekRequestTestCase {
+        args: Vec<&'static str>,
+        exp: WeekRequest,
+    }
+
+    #[test]
+    fn test_week_request() {
+        // A thursday in ISO week 37.
+        let today = NaiveDate::from_ymd_opt(2024, 9, 12).unwrap();
+        let iso = |week, year| WeekRequest::new(week, year).unwrap();
+
+        let test_cases = vec![
+            WeekRequestTestCase {
+                args: vec![],
+                exp: iso(37, 2024),
+            },
+            WeekRequestTestCase {
+                args: vec!["--this-week"],
+                exp: iso(37, 2024),
+            },
+            WeekRequestTestCase {
+                args: vec!["--next-week"],
+                exp: iso(38, 2024),
+            },
+            WeekRequestTestCase {
+                args: vec!["--offset", "-2"],
+                exp: iso(35, 2024),
+            },
+            WeekRequestTestCase {
+                args: vec!["--week", "5"],
+                exp: iso(5, 2024),
+            },
+            WeekRequestTestCase {
+                args: vec!["--week", "53", "--year", "2020"],
+                exp: iso(53, 2020),
+            },
+            WeekRequestTestCase {
+                args: vec!["--week", "52", "--year", "2024", "--offset", "1"],
+                exp: iso(1, 2025),
+            },
+            WeekRequestTestCase {
+                args: vec!["--date", "2024-12-31"],
+                exp: iso(1, 2025),
+            },
+            WeekRequestTestCase {
+                args: vec!["--next-week", "--first-weekday", "sunday"],
+                exp: WeekRequest::new_with_start(38, 2024, WeekStart::Sunday).unwrap(),
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut cli = vec![
+                "snapgenda",
+                "--calendar-email",
+                "jane.doe@example.com",
+                "--provider",
+                "google-calendar",
+            ];
+            cli.extend(&test_case.args);
+
+            let args = Args::try_parse_from(cli).unwrap();
+            let wr = args.week_request(today).unwrap();
+            assert_eq!(test_case.exp, wr, "{:?}", test_case.args);
+        }
+    }
+
+    #[test]
+    fn test_week_request_invalid() {
+        let test_cases = vec![
+            vec!["--week", "5", "--date", "2024-09-12"],
+            vec!["--this-week", "--next-week"],
+            vec!["--year", "2024"],
+            vec!["--date", "12.09.2024"],
+            vec!["--week", "1", "--year", "300000"],
+            vec!["--week", "1", "--year", "0"],
+        ];
+
+        for test_case in test_cases {
+            let mut cli = vec![
+                "snapgenda",
+                "--calendar-email",
+                "jane.doe@example.com",
+                "--provider",
+                "google-calendar",
+            ];
+            cli.extend(&test_case);
+
+            assert!(Args::try_parse_from(cli).is_err(), "{:?}", test_case);
+        }
+
+        let today = NaiveDate::from_ymd_opt(2024, 9, 12).unwrap();
+        let test_cases = vec![
+            vec!["--week", "53"],
+            vec!["--offset", "999999999999"],
+            vec!["--offset", "-999999999999"],
+            vec!["--date", "+200000-01-01"],
+        ];
+
+        for test_case in test_cases {
+            let mut cli = vec![
+                "snapgenda",
+                "--calendar-email",
+                "jane.doe@example.com",
+                "--provider",
+                "google-calendar",
+            ];
+            cli.extend(&test_case);
+
+            let args = Args::try_parse_from(cli).unwrap();
+            assert!(
+                matches!(args.week_request(today), Err(Error::Week(_))),
+                "{:?}",
+                test_case
+            );
+        }
+    }
+
+    #[test]
+    fn test_time_zone() {
+        let cli =
+            |tz: &'static str| Args::try_parse_from(["snapgenda", "--provider", "ics", "--tz", tz]);
+
+        let args = cli("America/New_York").unwrap();
+        assert_eq!(
+            Ok(chrono_tz::America::New_York),
+            args.time_zone().map_err(|e| e.to_string())
+        );
+
+        assert!(cli("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn test_hours_and_granularity() {
+        let args = Args::try_parse_from(["snapgenda", "--provider", "ics"]).unwrap();
+        assert_eq!(HourRange::default(), args.hours);
+        assert_eq!(60, args.row_minutes());
+
+        let args = Args::try_parse_from([
+            "snapgenda",
+            "--provider",
+            "ics",
+            "--hours",
+            "08-19",
+            "--granularity",
+            "15",
+        ])
+        .unwrap();
+        assert_eq!(HourRange::new(8, 19).unwrap(), args.hours);
+        assert_eq!(15, args.row_minutes());
+
+        for invalid in [["--hours", "19-08"], ["--granularity", "20"]] {
+            let mut cli = vec!["snapgenda", "--provider", "ics"];
+            cli.extend(invalid);
+            assert!(Args::try_parse_from(cli).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_theme() {
+        let args = Args::try_parse_from(["snapgenda", "--provider", "ics"]).unwrap();
+        assert_eq!(None, args.theme);
+
+        let args =
+            Args::try_parse_from(["snapgenda", "--provider", "ics", "--theme", "unicode"]).unwrap();
+        assert_eq!(Some(ThemeName::Unicode), args.theme);
+
+        assert!(
+            Args::try_parse_from(["snapgenda", "--provider", "ics", "--theme", "fancy"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_privacy() {
+        let args = Args::try_parse_from(["snapgenda", "--provider", "ics"]).unwrap();
+        assert_eq!(Privacy::BusyOnly, args.privacy);
+
+        for (value, exp) in [
+            ("busy-only", Privacy::BusyOnly),
+            ("titles", Privacy::Titles),
+            ("redacted", Privacy::Redacted),
+        ] {
+            let args = Args::try_parse_from(["snapgenda", "--provider", "ics", "--privacy", value])
+                .unwrap();
+            assert_eq!(exp, args.privacy, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_auth_subcommand() {
+        let args = Args::try_parse_from(["snapgenda", "auth", "status"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Auth {
+                action: AuthAction::Status
+            })
+        ));
+
+        let args = Args::try_parse_from(["snapgenda", "auth", "logout"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Auth {
+                action: AuthAction::Logout
+            })
+        ));
+
+        assert!(
+            Args::try_parse_from(["snapgenda", "--calendar-email", "jane.doe@example.com"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_calendars() {
+        let args = Args::try_parse_from([
+            "snapgenda",
+            "--provider",
+            "google-calendar",
+            "--google-id",
+            "id",
+            "--google-secret",
+            "secret",
+            "--calendar-email",
+            "jane.doe@example.com,john.doe@example.com",
+            "--calendar-email",
+            "max@example.com",
+            "--view",
+            "side-by-side",
+            "--free-busy",
+        ])
+        .unwrap();
+        assert_eq!(View::SideBySide, args.view);
+        let google_args = GoogleArgs::new(args).unwrap();
+        assert_eq!(
+            vec![
+                "jane.doe@example.com",
+                "john.doe@example.com",
+                "max@example.com"
+            ],
+            google_args.calendar_emails
+        );
+        assert!(google_args.free_busy);
+        assert_eq!(RetryPolicy::default(), google_args.retry_policy);
+
+        let args = Args::try_parse_from([
+            "snapgenda",
+            "--provider",
+            "google-calendar",
+            "--google-id",
+            "id",
+            "--google-secret",
+            "secret",
+            "--calendar-email",
+            "jane.doe@example.com",
+            "--max-retries",
+            "0",
+            "--timeout",
+            "5",
+        ])
+        .unwrap();
+        let retry_policy = GoogleArgs::new(args).unwrap().retry_policy;
+        assert_eq!(0, retry_policy.max_retries);
+        assert_eq!(Duration::from_secs(5), retry_policy.timeout);
+        assert!(
+            Args::try_parse_from(["snapgenda", "--provider", "ics", "--timeout", "0"]).is_err()
+        );
+
+        let args = Args::try_parse_from(["snapgenda", "--provider", "google-calendar"]).unwrap();
+        assert_eq!(View::Merged, args.view);
+        assert!(matches!(GoogleArgs::new(args), Err(Error::GoogleArgs(_))));
+    }
+
+    #[test]
+    fn test_google_service_account() {
+        let args = Args::try_parse_from([
+            "snapgenda",
+            "--provider",
+            "google-calendar",
+            "--calendar-email",
+            "team@example.com",
+            "--google-service-account-key",
+            "key.json",
+            "--google-impersonate",
+            "jane.doe@example.com",
+        ])
+        .unwrap();
+        let GoogleAuth::ServiceAccount { key, impersonate } = GoogleArgs::new(args).unwrap().auth
+        else {
+            panic!("expected service account");
+        };
+        assert_eq!(PathBuf::from("key.json"), key);
+        assert_eq!(Some("jane.doe@example.com".to_string()), impersonate);
+
+        let invalid = [
+            vec!["--google-impersonate", "jane.doe@example.com"],
+            vec![
+                "--google-service-account-key",
+                "key.json",
+                "--google-id",
+                "id",
+            ],
+            vec!["--google-service-account-key", "key.json", "--manual-auth"],
+        ];
+        for args in invalid {
+            let mut argv = vec!["snapgenda", "--provider", "google-calendar"];
+            argv.extend(&args);
+            assert!(Args::try_parse_from(&argv).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_free_subcommand() {
+        let args = Args::try_parse_from(["snapgenda", "--provider", "ics", "free"]).unwrap();
+        let Some(Command::Free(free_args)) = args.command else {
+            panic!("expected free, got {:?}", args.command);
+        };
+        let query = free_args.query();
+        assert_eq!(FreeSlotQuery::default().working_hours, query.working_hours);
+        assert_eq!(TimeDelta::minutes(30), query.min_duration);
+        assert_eq!(TimeDelta::zero(), query.buffer);
+        assert_eq!(
+            vec![WeekDay::Saturday, WeekDay::Sunday],
+            query.excluded_days
+        );
+
+        let args = Args::try_parse_from([
+            "snapgenda",
+            "--provider",
+            "ics",
+            "free",
+            "--min-duration",
+            "60",
+            "--buffer",
+            "10",
+            "--working-hours",
+            "08-18",
+            "--exclude-days",
+            "fri, sat,sun",
+        ])
+        .unwrap();
+        let Some(Command::Free(free_args)) = args.command else {
+            panic!("expected free, got {:?}", args.command);
+        };
+        assert_eq!(HourRange::new(8, 18).unwrap(), free_args.working_hours);
+        assert_eq!(TimeDelta::minutes(60), free_args.query().min_duration);
+        assert_eq!(TimeDelta::minutes(10), free_args.query().buffer);
+        assert_eq!(
+            WeekDays(vec![WeekDay::Friday, WeekDay::Saturday, WeekDay::Sunday]),
+            free_args.exclude_days
+        );
+
+        // The subcommand lifts the requirement, the provider is checked when it is needed.
+        let args = Args::try_parse_from(["snapgenda", "free"]).unwrap();
+        assert!(matches!(args.provider(), Err(Error::MissingProvider)));
+    }
+
+    #[test]
+    fn test_week_days_from_str() {
+        assert_eq!(Ok(WeekDays(vec![])), "none".parse());
+        assert_eq!(
+            Ok(WeekDays(vec![WeekDay::Monday, WeekDay::Wednesday])),
+            "mon,wednesday".parse()
+        );
+        assert!("mon,funday".parse::<WeekDays>().is_err());
+        assert!("".parse::<WeekDays>().is_err());
+    }
+
+    #[test]
+    fn test_ics_args() {
+        let args = Args::try_parse_from([
+            "snapgenda",
+            "--provider",
+            "ics",
+            "--ics-file",
+            "work.ics",
+            "--ics-file",
+            "private.ics",
+        ])
+        .unwrap();
+        let ics_args = IcsArgs::new(args).unwrap();
+        assert_eq!(
+            vec![PathBuf::from("work.ics"), PathBuf::from("private.ics")],
+            ics_args.files
+        );
+
+        let args = Args::try_parse_from(["snapgenda", "--provider", "ics"]).unwrap();
+        assert!(matches!(IcsArgs::new(args), Err(Error::IcsArgs(_))));
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::theme::{StateStyle, Theme, ThemeName};
+
+#[derive(Debug)]
+pub enum Error {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Read(path, e) => {
+                write!(f, "Could not read config {}: {}", path.display(), e)
+            }
+            Error::Parse(path, e) => {
+                write!(f, "Invalid config {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Settings from ~/.config/snapgenda/config.toml on Linux, e.g.
+///
+/// ```toml
+/// theme = "unicode"
+///
+/// [themes.unicode]
+/// busy = { fill = "▓▓▓▓▓▓", block_end = "▓▓▓▓▓▓" }
+///
+/// [themes.ansi]
+/// busy = { background = "48;5;202" }
+/// ```
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Used when --theme is not given.
+    pub theme: Option<ThemeName>,
+    #[serde(default)]
+    pub themes: HashMap<ThemeName, ThemeOverrides>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeOverrides {
+    top: Option<String>,
+    bottom: Option<String>,
+    side: Option<String>,
+    free: Option<StateOverrides>,
+    partial: Option<StateOverrides>,
+    busy: Option<StateOverrides>,
+    tentative: Option<StateOverrides>,
+    out_of_office: Option<StateOverrides>,
+    focus: Option<StateOverrides>,
+    working_elsewhere: Option<StateOverrides>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateOverrides {
+    fill: Option<String>,
+    block_end: Option<String>,
+    background: Option<String>,
+    banner_open: Option<String>,
+    banner_close: Option<String>,
+}
+
+impl StateOverrides {
+    fn apply(&self, style: &mut StateStyle) {
+        override_with(&mut style.fill, &self.fill);
+        override_with(&mut style.block_end, &self.block_end);
+        override_with(&mut style.banner_open, &self.banner_open);
+        override_with(&mut style.banner_close, &self.banner_close);
+        if self.background.is_some() {
+            style.background.clone_from(&self.background);
+        }
+    }
+}
+
+fn override_with(value: &mut String, with: &Option<String>) {
+    if let Some(with) = with {
+        value.clone_from(with);
+    }
+}
+
+impl Config {
+    /// Returns the default config when no path is given and the config file does not exist.
+    pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(Error::Read(path, e)),
+        };
+
+        let config: Config =
+            toml::from_str(&content).map_err(|e| Error::Parse(path.clone(), e.to_string()))?;
+        for name in config.themes.keys() {
+            config
+                .apply(*name, Theme::new(*name))
+                .validate()
+                .map_err(|e| Error::Parse(path.clone(), e))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Picks the theme from --theme, the config or ASCII, in that order. Without colors the
+    /// ANSI theme falls back to Unicode.
+    pub fn theme(&self, cli: Option<ThemeName>, color: bool) -> Theme {
+        let name = match cli.or(self.theme).unwrap_or(ThemeName::Ascii) {
+            ThemeName::Ansi if !color => ThemeName::Unicode,
+            name => name,
+        };
+
+        self.apply(name, Theme::new(name))
+    }
+
+    fn apply(&self, name: ThemeName, mut theme: Theme) -> Theme {
+        let Some(overrides) = self.themes.get(&name) else {
+            return theme;
+        };
+
+        override_with(&mut theme.top, &overrides.top);
+        override_with(&mut theme.bottom, &overrides.bottom);
+        override_with(&mut theme.side, &overrides.side);
+        for (style, overrides) in [
+            (&mut theme.free, &overrides.free),
+            (&mut theme.partial, &overrides.partial),
+            (&mut theme.busy, &overrides.busy),
+            (&mut theme.tentative, &overrides.tentative),
+            (&mut theme.out_of_office, &overrides.out_of_office),
+            (&mut theme.focus, &overrides.focus),
+            (&mut theme.working_elsewhere, &overrides.working_elsewhere),
+        ] {
+            if let Some(overrides) = overrides {
+                overrides.apply(style);
+            }
+        }
+
+        theme
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("snapgenda").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(content: &str) -> Result<Config, Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, content).unwrap();
+
+        Config::load(Some(&path))
+    }
+
+    #[test]
+    fn test_load() {
+        let config = load(
+            r#"
+theme = "ansi"
+
+[themes.ansi]
+busy = { background = "48;5;202" }
+
+[themes.unicode]
+busy = { fill = "▓▓▓▓▓▓", block_end = "▓▓▓▓▓▓" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(Some(ThemeName::Ansi), config.theme);
+
+        let ansi = config.theme(None, true);
+        assert!(ansi.color);
+        assert_eq!(Some("48;5;202".to_string()), ansi.busy.background);
+        assert_eq!(Theme::ansi().free, ansi.free);
+
+        // Without colors the unicode overrides apply.
+        let unicode = config.theme(None, false);
+        assert!(!unicode.color);
+        assert_eq!("▓▓▓▓▓▓", unicode.busy.fill);
+
+        assert_eq!(Theme::ascii(), config.theme(Some(ThemeName::Ascii), true));
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let test_cases = vec![
+            "theme = \"fancy\"",
+            "colour = true",
+            "[themes.ascii]\ntop = \"+--+\"",
+            "[themes.ascii]\nbusy = { fill = \"Busy\" }",
+        ];
+
+        for test_case in test_cases {
+            assert!(
+                matches!(load(test_case), Err(Error::Parse(_, _))),
+                "{}",
+                test_case
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        assert!(matches!(Config::load(Some(&path)), Err(Error::Read(_, _))));
+    }
+}
@@ -1,26 +1,71 @@
 use std::{
     self,
+    cell::RefCell,
+    collections::BTreeMap,
     fmt::{self},
     io::{self, Write},
+    rc::Rc,
+    time::Duration,
 };
 
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
 use oauth2::{
-    basic::BasicClient, reqwest::http_client, AuthUrl, AuthorizationCode, ClientId, ClientSecret,
-    CsrfToken, ErrorResponse, PkceCodeChallenge, RedirectUrl, Scope, TokenResponse, TokenUrl,
+    basic::{BasicClient, BasicTokenResponse},
+    url::Url,
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, ErrorResponse,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, RefreshToken, Scope, TokenResponse, TokenUrl,
 };
 use reqwest::{
-    blocking::Client,
-    header::{AUTHORIZATION, CONTENT_TYPE},
+    blocking::Response,
+    header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use snapgenda::{Availability, CalendarProvider};
+
+use crate::{
+    http::{self, HttpClient},
+    loopback::{self, LoopbackListener},
+    service_account::{self, ServiceAccountKey},
+    token_cache::{CachedToken, TokenCache},
 };
 
 const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/auth";
 const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const CALENDAR_API_URL: &str = "https://www.googleapis.com/calendar/v3";
+const CALENDAR_SCOPE: &str = "https://www.googleapis.com/auth/calendar.readonly";
+// Used when the loopback listener is not available. The browser fails to load it and the user
+// copies the URL from the address bar.
+const MANUAL_REDIRECT_URL: &str = "http://127.0.0.1";
 
-#[derive(Debug)]
+// Google Calendar shows events without a title like this.
+const NO_TITLE: &str = "(No title)";
+// Events per page of events.list, Google's default.
+const MAX_RESULTS: u32 = 250;
+
+#[derive(Debug, Clone)]
 pub enum Error {
+    /// Signing in failed, e.g. the consent was not given or the client is unknown.
     Unauthorized(String),
-    InputError(String),
+    /// Google rejected the access token.
+    TokenExpired(String),
+    /// The request did not reach Google or no response came back.
+    Network(String),
+    /// A response status without a more specific variant, with the message Google sent.
+    Http {
+        status: u16,
+        body: String,
+    },
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// The calendar does not exist or is not shared at all.
+    NotFound(String),
+    /// The calendar exists but its events may not be read, e.g. it is only shared with
+    /// free/busy permission.
+    PermissionDenied(String),
+    Input(String),
+    Parse(String),
 }
 
 impl fmt::Display for Error {
@@ -29,9 +74,55 @@ impl fmt::Display for Error {
             Error::Unauthorized(e) => {
                 write!(f, "Unauthorized error: {}", e)
             }
-            Error::InputError(e) => {
+            Error::TokenExpired(e) => {
+                write!(f, "Access token rejected: {}", e)
+            }
+            Error::Network(e) => {
+                write!(f, "Could not reach Google: {}", e)
+            }
+            Error::Http { status, body } => {
+                write!(f, "Google answered {}: {}", status, body)
+            }
+            Error::RateLimited {
+                retry_after: Some(retry_after),
+            } => {
+                write!(
+                    f,
+                    "Rate limited by Google, retry after {}s",
+                    retry_after.as_secs()
+                )
+            }
+            Error::RateLimited { retry_after: None } => {
+                write!(f, "Rate limited by Google")
+            }
+            Error::NotFound(calendar_id) => {
+                write!(f, "Calendar {} not found", calendar_id)
+            }
+            Error::PermissionDenied(e) => {
+                write!(f, "Permission denied: {}", e)
+            }
+            Error::Input(e) => {
                 write!(f, "Input error: {}", e)
             }
+            Error::Parse(e) => {
+                write!(f, "Parse error: {}", e)
+            }
+        }
+    }
+}
+
+impl Error {
+    /// What the user can do about the error.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::Unauthorized(_) => Some("Check --google-id and --google-secret and grant access when asked. Service accounts need domain-wide delegation for --google-impersonate."),
+            Error::TokenExpired(_) => Some("Run `snapgenda auth logout` and try again to sign in anew."),
+            Error::Network(_) => Some("Check your internet connection and proxy settings."),
+            Error::Http { status, .. } if *status >= 500 => Some("Google has a problem, try again later."),
+            Error::RateLimited { .. } => Some("Wait a moment and try again, or request fewer calendars at once."),
+            Error::NotFound(_) => Some("Check the spelling of --calendar-email and that the calendar is shared with you."),
+            Error::PermissionDenied(_) => Some("Ask the owner to share the calendar with you, or use --free-busy if only free/busy is shared."),
+            Error::Http { .. } | Error::Input(_) | Error::Parse(_) => None,
         }
     }
 }
@@ -42,134 +133,1601 @@ where
     TE: ErrorResponse + 'static,
 {
     fn from(error: oauth2::RequestTokenError<RE, TE>) -> Self {
-        Error::Unauthorized(error.to_string())
+        match error {
+            oauth2::RequestTokenError::Request(e) => Error::Network(e.to_string()),
+            oauth2::RequestTokenError::Parse(e, _) => Error::Parse(e.to_string()),
+            e => Error::Unauthorized(e.to_string()),
+        }
     }
 }
 
 impl From<oauth2::url::ParseError> for Error {
     fn from(error: oauth2::url::ParseError) -> Self {
-        Error::Unauthorized(error.to_string())
+        Error::Input(error.to_string())
     }
 }
 
 impl From<reqwest::Error> for Error {
     fn from(error: reqwest::Error) -> Self {
+        Error::Network(error.to_string())
+    }
+}
+
+// Error body of the Calendar API.
+// https://developers.google.com/calendar/api/guides/errors
+#[derive(Deserialize)]
+struct ApiErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    errors: Vec<ApiErrorDetail>,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorDetail {
+    #[serde(default)]
+    reason: String,
+}
+
+/// Turns a response that is not a success into an error. Google reports rate limits either as
+/// 429 or as 403 with a rate limit reason.
+fn error_from_response(
+    status: StatusCode,
+    retry_after: Option<&str>,
+    body: &str,
+    calendar_id: &str,
+) -> Error {
+    let (message, reasons) = match serde_json::from_str::<ApiErrorResponse>(body) {
+        Ok(response) => (
+            response.error.message,
+            response
+                .error
+                .errors
+                .into_iter()
+                .map(|e| e.reason)
+                .collect(),
+        ),
+        Err(_) => (body.trim().to_string(), Vec::new()),
+    };
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && reasons
+                .iter()
+                .any(|r| http::RATE_LIMIT_REASONS.contains(&r.as_str())));
+
+    match status {
+        _ if rate_limited => Error::RateLimited {
+            retry_after: retry_after.and_then(|s| http::parse_retry_after(s, Utc::now())),
+        },
+        StatusCode::UNAUTHORIZED => Error::TokenExpired(message),
+        StatusCode::FORBIDDEN => Error::PermissionDenied(format!("{}: {}", calendar_id, message)),
+        StatusCode::NOT_FOUND => Error::NotFound(calendar_id.to_string()),
+        _ => Error::Http {
+            status: status.as_u16(),
+            body: message,
+        },
+    }
+}
+
+fn check_response(response: Response, calendar_id: &str) -> Result<Response, Error> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = response.text()?;
+    Err(error_from_response(
+        status,
+        retry_after.as_deref(),
+        &body,
+        calendar_id,
+    ))
+}
+
+impl From<loopback::Error> for Error {
+    fn from(error: loopback::Error) -> Self {
         Error::Unauthorized(error.to_string())
     }
 }
 
+impl From<service_account::Error> for Error {
+    fn from(error: service_account::Error) -> Self {
+        match error {
+            service_account::Error::Request(_) => Error::Network(error.to_string()),
+            e => Error::Unauthorized(e.to_string()),
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Self {
-        Error::InputError(error.to_string())
+        Error::Input(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Parse(error.to_string())
     }
 }
 
 impl std::error::Error for Error {}
 
+#[derive(Debug)]
 pub struct Calendar {
     pub events: Vec<Event>,
 }
 
+#[derive(Debug, PartialEq)]
 pub struct Event {
-    pub created: NaiveDateTime,
-    pub updated: NaiveDateTime,
-    pub summary: String,
-    pub from: NaiveDate,
-    pub to: NaiveDate,
-}
-
-pub fn fetch_calendar(calendar_args: CalendarArgs) -> Result<Calendar, Error> {
-    let auth_secret = do_auth(calendar_args.client_args)?;
-    let url = format!(
-        "https://www.googleapis.com/calendar/v3/calendars/{}/events",
-        calendar_args.calendar_id
-    );
-
-    let client = Client::new();
-    let response = client
-        .get(&url)
-        .query(&[
-            (
-                "timeMin",
-                // 2024-09-08T00:00:00+02:00
-                format!(
-                    "{}+00:00",
-                    calendar_args
-                        .from
-                        .and_hms_opt(0, 0, 0)
-                        .unwrap()
-                        .format("%Y-%m-%dT%H:%M:%S")
-                ),
-            ),
-            (
-                "timeMax",
-                format!(
-                    "{}+00:00",
-                    calendar_args
-                        .to
-                        .and_hms_opt(0, 0, 0)
-                        .unwrap()
-                        .format("%Y-%m-%dT%H:%M:%S")
+    /// None for busy intervals, their title is unknown.
+    pub summary: Option<String>,
+    pub from: EventTime,
+    pub to: EventTime,
+    pub availability: Availability,
+    pub private: bool,
+}
+
+impl Event {
+    pub fn to_event(&self) -> snapgenda::Event {
+        snapgenda::Event {
+            summary: self.summary.clone(),
+            start: self.from.to_event_time(),
+            end: self.to.to_event_time(),
+            availability: self.availability,
+            private: self.private,
+        }
+    }
+}
+
+/// Google either sends a date for all-day events or a date time for timed events.
+#[derive(Debug, PartialEq)]
+pub enum EventTime {
+    Date(NaiveDate),
+    DateTime(DateTime<FixedOffset>),
+}
+
+impl EventTime {
+    fn to_event_time(&self) -> snapgenda::EventTime {
+        match self {
+            EventTime::Date(d) => snapgenda::EventTime::Date(*d),
+            EventTime::DateTime(dt) => snapgenda::EventTime::DateTime(dt.to_utc()),
+        }
+    }
+}
+
+// Subset of the Calendar v3 events.list response we care about. Unknown fields are ignored
+// by serde.
+// https://developers.google.com/calendar/api/v3/reference/events/list
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventsResponse {
+    #[serde(default)]
+    items: Vec<EventResource>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventResource {
+    id: String,
+    #[serde(default)]
+    status: EventStatus,
+    summary: Option<String>,
+    #[serde(default)]
+    visibility: Visibility,
+    #[serde(default)]
+    transparency: Transparency,
+    #[serde(default)]
+    event_type: EventType,
+    #[serde(default)]
+    attendees: Vec<Attendee>,
+    // Cancelled events may come without start and end.
+    start: Option<EventDateTime>,
+    end: Option<EventDateTime>,
+}
+
+impl EventResource {
+    /// The event type wins, then declined or transparent events are free and unanswered or
+    /// tentative invites are tentative.
+    fn availability(&self) -> Availability {
+        match self.event_type {
+            EventType::OutOfOffice => return Availability::OutOfOffice,
+            EventType::FocusTime => return Availability::Focus,
+            EventType::WorkingLocation => return Availability::WorkingElsewhere,
+            EventType::Default | EventType::Other => {}
+        }
+
+        let response = self
+            .attendees
+            .iter()
+            .find(|a| a.is_self)
+            .map(|a| a.response_status);
+        if self.transparency == Transparency::Transparent
+            || response == Some(ResponseStatus::Declined)
+        {
+            return Availability::Free;
+        }
+
+        if self.status == EventStatus::Tentative
+            || matches!(
+                response,
+                Some(ResponseStatus::Tentative | ResponseStatus::NeedsAction)
+            )
+        {
+            return Availability::Tentative;
+        }
+
+        Availability::Busy
+    }
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Transparency {
+    #[default]
+    Opaque,
+    Transparent,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum EventType {
+    #[default]
+    Default,
+    OutOfOffice,
+    FocusTime,
+    WorkingLocation,
+    // E.g. birthdays and events from Gmail.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Attendee {
+    #[serde(default, rename = "self")]
+    is_self: bool,
+    response_status: ResponseStatus,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum ResponseStatus {
+    NeedsAction,
+    Declined,
+    Tentative,
+    Accepted,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum EventStatus {
+    #[default]
+    Confirmed,
+    Tentative,
+    Cancelled,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Visibility {
+    #[default]
+    Default,
+    Public,
+    Private,
+    Confidential,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventDateTime {
+    date: Option<NaiveDate>,
+    date_time: Option<DateTime<FixedOffset>>,
+}
+
+impl EventDateTime {
+    fn to_event_time(&self, event_id: &str) -> Result<EventTime, Error> {
+        match (self.date_time, self.date) {
+            (Some(dt), _) => Ok(EventTime::DateTime(dt)),
+            (None, Some(d)) => Ok(EventTime::Date(d)),
+            (None, None) => Err(Error::Parse(format!(
+                "event {} has neither date nor dateTime",
+                event_id
+            ))),
+        }
+    }
+}
+
+/// One response of events.list, more events follow while there is a next page token.
+struct Page {
+    events: Vec<Event>,
+    next_page_token: Option<String>,
+}
+
+impl TryFrom<EventsResponse> for Page {
+    type Error = Error;
+
+    fn try_from(response: EventsResponse) -> Result<Self, Self::Error> {
+        let mut events: Vec<Event> = Vec::new();
+
+        for item in response.items {
+            if item.status == EventStatus::Cancelled {
+                continue;
+            }
+
+            let (start, end) = match (&item.start, &item.end) {
+                (Some(start), Some(end)) => (start, end),
+                _ => {
+                    return Err(Error::Parse(format!(
+                        "event {} is missing start or end",
+                        item.id
+                    )))
+                }
+            };
+
+            events.push(Event {
+                availability: item.availability(),
+                from: start.to_event_time(&item.id)?,
+                to: end.to_event_time(&item.id)?,
+                summary: Some(item.summary.unwrap_or(NO_TITLE.to_string())),
+                private: matches!(
+                    item.visibility,
+                    Visibility::Private | Visibility::Confidential
                 ),
-            ),
-        ])
-        .header(AUTHORIZATION, format!("Bearer {}", auth_secret))
-        .header(CONTENT_TYPE, "application/json")
-        .send()?;
+            });
+        }
+
+        Ok(Page {
+            events,
+            next_page_token: response.next_page_token,
+        })
+    }
+}
+
+// Request and response of freeBusy.query.
+// https://developers.google.com/calendar/api/v3/reference/freebusy/query
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct FreeBusyRequest {
+    time_min: DateTime<Utc>,
+    time_max: DateTime<Utc>,
+    items: Vec<FreeBusyRequestItem>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct FreeBusyRequestItem {
+    id: String,
+}
+
+impl FreeBusyRequest {
+    fn new(calendar_ids: &[String], from: DateTime<Utc>, to: DateTime<Utc>) -> FreeBusyRequest {
+        FreeBusyRequest {
+            time_min: from,
+            time_max: to,
+            items: calendar_ids
+                .iter()
+                .map(|id| FreeBusyRequestItem { id: id.clone() })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FreeBusyResponse {
+    #[serde(default)]
+    calendars: BTreeMap<String, FreeBusyCalendar>,
+}
+
+#[derive(Deserialize)]
+struct FreeBusyCalendar {
+    #[serde(default)]
+    busy: Vec<BusyInterval>,
+    #[serde(default)]
+    errors: Vec<FreeBusyError>,
+}
+
+#[derive(Deserialize)]
+struct BusyInterval {
+    start: DateTime<FixedOffset>,
+    end: DateTime<FixedOffset>,
+}
 
-    if !response.status().is_success() {
-        return Err(Error::Unauthorized("".to_string()));
+#[derive(Deserialize)]
+struct FreeBusyError {
+    reason: String,
+}
+
+/// Busy intervals turn into events without a title, freeBusy.query only tells when a calendar
+/// is busy, not why. A calendar that could not be queried only fails on its own.
+fn parse_free_busy(body: &str) -> Result<BTreeMap<String, Result<Calendar, Error>>, Error> {
+    let response: FreeBusyResponse = serde_json::from_str(body)?;
+
+    let mut out = BTreeMap::new();
+    for (id, calendar) in response.calendars {
+        let calendar = match calendar.errors.first() {
+            Some(error) => Err(match error.reason.as_str() {
+                "notFound" => Error::NotFound(id.clone()),
+                reason => Error::PermissionDenied(format!("free/busy of {}: {}", id, reason)),
+            }),
+            None => Ok(Calendar {
+                events: calendar
+                    .busy
+                    .iter()
+                    .map(|busy| Event {
+                        summary: None,
+                        from: EventTime::DateTime(busy.start),
+                        to: EventTime::DateTime(busy.end),
+                        availability: Availability::Busy,
+                        private: false,
+                    })
+                    .collect(),
+            }),
+        };
+        out.insert(id, calendar);
     }
 
-    // TODO: Parse json.
-    let events: serde_json::Value = response.json()?;
+    Ok(out)
+}
 
-    Ok(Calendar { events: todo!() })
+fn parse_page(body: &str) -> Result<Page, Error> {
+    let response: EventsResponse = serde_json::from_str(body)?;
+    Page::try_from(response)
+}
+
+/// Returns the events of the calendar overlapping from until to. Pages are requested while
+/// iterating, so only one of them is held at a time.
+pub fn list_events(
+    calendar_args: &CalendarArgs,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Events, Error> {
+    let auth_secret = do_auth(&calendar_args.client_args, &calendar_args.token_cache)?;
+
+    Events::new(
+        calendar_args.client_args.http.clone(),
+        &calendar_args.client_args.endpoints.calendar_api_url,
+        calendar_args.calendar_id.clone(),
+        auth_secret,
+        from,
+        to,
+    )
+}
+
+/// Calendar ids may contain characters such as # that need escaping in the path, e.g.
+/// en.german#holiday@group.v.calendar.google.com.
+fn events_url(api_url: &str, calendar_id: &str) -> Result<Url, Error> {
+    let invalid = |e: String| Error::Input(format!("invalid calendar API URL {}: {}", api_url, e));
+    let mut url = Url::parse(api_url).map_err(|e| invalid(e.to_string()))?;
+    url.path_segments_mut()
+        .map_err(|_| invalid("cannot have a path".to_string()))?
+        .pop_if_empty()
+        .extend(["calendars", calendar_id, "events"]);
+
+    Ok(url)
+}
+
+enum NextPage {
+    First,
+    Token(String),
+    Done,
+}
+
+/// Iterator over the events of events.list. Iteration ends after the first error.
+pub struct Events {
+    client: HttpClient,
+    url: Url,
+    calendar_id: String,
+    auth_secret: String,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    page: std::vec::IntoIter<Event>,
+    next_page: NextPage,
+}
+
+impl Events {
+    fn new(
+        client: HttpClient,
+        api_url: &str,
+        calendar_id: String,
+        auth_secret: String,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Events, Error> {
+        Ok(Events {
+            client,
+            url: events_url(api_url, &calendar_id)?,
+            calendar_id,
+            auth_secret,
+            from,
+            to,
+            page: Vec::new().into_iter(),
+            next_page: NextPage::First,
+        })
+    }
+
+    fn fetch_page(&self, page_token: Option<&str>) -> Result<Page, Error> {
+        let request = self
+            .client
+            .get(self.url.as_str())
+            .query(&events_query(self.from, self.to, page_token))
+            .header(AUTHORIZATION, format!("Bearer {}", self.auth_secret))
+            .header(CONTENT_TYPE, "application/json");
+        let response = self.client.send(request)?;
+
+        parse_page(&check_response(response, &self.calendar_id)?.text()?)
+    }
+}
+
+impl Iterator for Events {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.page.next() {
+                return Some(Ok(event));
+            }
+
+            let page_token = match std::mem::replace(&mut self.next_page, NextPage::Done) {
+                NextPage::First => None,
+                NextPage::Token(token) => Some(token),
+                NextPage::Done => return None,
+            };
+            match self.fetch_page(page_token.as_deref()) {
+                Ok(page) => {
+                    self.page = page.events.into_iter();
+                    if let Some(token) = page.next_page_token {
+                        self.next_page = NextPage::Token(token);
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Fetches the busy intervals of all calendars with a single freeBusy.query request.
+pub fn fetch_free_busy(
+    calendar_args: &CalendarArgs,
+    calendar_ids: &[String],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<BTreeMap<String, Result<Calendar, Error>>, Error> {
+    let auth_secret = do_auth(&calendar_args.client_args, &calendar_args.token_cache)?;
+
+    let client = &calendar_args.client_args.http;
+    let request = client
+        .post(&format!(
+            "{}/freeBusy",
+            calendar_args.client_args.endpoints.calendar_api_url
+        ))
+        .header(AUTHORIZATION, format!("Bearer {}", auth_secret))
+        .json(&FreeBusyRequest::new(calendar_ids, from, to));
+    // The query only reads, so it is safe to repeat.
+    let response = client.send_idempotent(request)?;
+
+    parse_free_busy(&check_response(response, &calendar_ids.join(", "))?.text()?)
+}
+
+// Recurring events are expanded into their instances by the server.
+fn events_query(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    page_token: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut query = vec![
+        ("timeMin", from.to_rfc3339()),
+        ("timeMax", to.to_rfc3339()),
+        ("singleEvents", "true".to_string()),
+        ("orderBy", "startTime".to_string()),
+        ("maxResults", MAX_RESULTS.to_string()),
+    ];
+    if let Some(page_token) = page_token {
+        query.push(("pageToken", page_token.to_string()));
+    }
+
+    query
 }
 
 pub struct CalendarArgs {
-    pub from: NaiveDate,
-    pub to: NaiveDate,
     pub calendar_id: String,
     pub client_args: ClientArgs,
+    pub token_cache: TokenCache,
+    /// Only read busy intervals, without trying to read events first.
+    pub free_busy: bool,
+}
+
+/// Busy intervals of a group of calendars. They are fetched in one request the first time any
+/// calendar of the group needs them.
+pub struct FreeBusy {
+    calendar_ids: Vec<String>,
+    fetched: RefCell<Option<FetchedFreeBusy>>,
+}
+
+struct FetchedFreeBusy {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    calendars: BTreeMap<String, Result<Calendar, Error>>,
+}
+
+impl FreeBusy {
+    pub fn new(calendar_ids: Vec<String>) -> FreeBusy {
+        FreeBusy {
+            calendar_ids,
+            fetched: RefCell::new(None),
+        }
+    }
+
+    fn events(
+        &self,
+        calendar_args: &CalendarArgs,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<snapgenda::Event>, Error> {
+        let mut fetched = self.fetched.borrow_mut();
+        if !fetched
+            .as_ref()
+            .is_some_and(|f| f.from == from && f.to == to)
+        {
+            *fetched = Some(FetchedFreeBusy {
+                from,
+                to,
+                calendars: fetch_free_busy(calendar_args, &self.calendar_ids, from, to)?,
+            });
+        }
+
+        let calendars = fetched.as_ref().map(|f| &f.calendars);
+        match calendars.and_then(|c| c.get(&calendar_args.calendar_id)) {
+            Some(Ok(calendar)) => Ok(calendar.events.iter().map(Event::to_event).collect()),
+            Some(Err(e)) => Err(e.clone()),
+            None => Err(Error::Parse(format!(
+                "free/busy response misses calendar {}",
+                calendar_args.calendar_id
+            ))),
+        }
+    }
+}
+
+pub struct GoogleProvider {
+    calendar_args: CalendarArgs,
+    free_busy: Rc<FreeBusy>,
+}
+
+impl GoogleProvider {
+    /// Calendars sharing free_busy have their busy intervals fetched together.
+    pub fn new(calendar_args: CalendarArgs, free_busy: Rc<FreeBusy>) -> GoogleProvider {
+        GoogleProvider {
+            calendar_args,
+            free_busy,
+        }
+    }
+}
+
+impl CalendarProvider for GoogleProvider {
+    /// Falls back to busy intervals when the events of the calendar may not be read.
+    fn fetch_events(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<snapgenda::Event>, Box<dyn std::error::Error>> {
+        if !self.calendar_args.free_busy {
+            let events: Result<Vec<Event>, Error> =
+                list_events(&self.calendar_args, from, to).and_then(Iterator::collect);
+            match events {
+                Ok(events) => return Ok(events.iter().map(Event::to_event).collect()),
+                Err(Error::PermissionDenied(e)) => {
+                    eprintln!("Showing free/busy only, reading events was denied: {}", e)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(self.free_busy.events(&self.calendar_args, from, to)?)
+    }
 }
 
 pub struct ClientArgs {
-    pub id: String,
-    pub secret: String,
+    pub credentials: Credentials,
+    pub endpoints: Endpoints,
+    /// Shared by all calendars, retries failed requests.
+    pub http: HttpClient,
+}
+
+#[derive(Clone)]
+pub enum Credentials {
+    /// OAuth client of an installed app, the user grants access in the browser.
+    User {
+        id: String,
+        secret: String,
+        /// Paste the redirect URL instead of receiving it on a loopback listener, for headless
+        /// environments.
+        manual_auth: bool,
+    },
+    /// Signs in without user interaction, e.g. for cron jobs.
+    ServiceAccount {
+        key: ServiceAccountKey,
+        /// User to act as through domain-wide delegation. Without it only calendars shared
+        /// with the service account itself can be read.
+        subject: Option<String>,
+    },
 }
 
-fn do_auth(client_args: ClientArgs) -> Result<String, Error> {
-    let client_id = ClientId::new(client_args.id);
-    let client_secret = ClientSecret::new(client_args.secret);
-    let auth_url = AuthUrl::new(AUTH_URL.to_string())?;
-    let token_url = TokenUrl::new(TOKEN_URL.to_string())?;
+/// Base URLs of the Google APIs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub auth_url: String,
+    pub token_url: String,
+    /// Calendar API v3, without a trailing slash.
+    pub calendar_api_url: String,
+}
 
-    let client = BasicClient::new(client_id, Some(client_secret), auth_url, Some(token_url))
-        .set_redirect_uri(RedirectUrl::new("urn:ietf:wg:oauth:2.0:oob".to_string())?);
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints {
+            auth_url: AUTH_URL.to_string(),
+            token_url: TOKEN_URL.to_string(),
+            calendar_api_url: CALENDAR_API_URL.to_string(),
+        }
+    }
+}
 
-    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+impl Endpoints {
+    /// Debug builds let the integration tests point the endpoints at a local server with the
+    /// SNAPGENDA_GOOGLE_AUTH_URL, SNAPGENDA_GOOGLE_TOKEN_URL and SNAPGENDA_GOOGLE_API_URL
+    /// environment variables. Release builds always talk to Google, so the environment cannot
+    /// redirect tokens elsewhere.
+    pub fn from_env() -> Endpoints {
+        if cfg!(debug_assertions) {
+            Endpoints::from_vars(|name| std::env::var(name).ok())
+        } else {
+            Endpoints::default()
+        }
+    }
 
-    let (auth_url, _) = client
-        .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new(
-            "https://www.googleapis.com/auth/calendar.readonly".to_string(),
-        ))
-        .set_pkce_challenge(pkce_challenge)
-        .url();
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Endpoints {
+        let defaults = Endpoints::default();
+        let var =
+            |name: &str, default: String| var(name).filter(|v| !v.is_empty()).unwrap_or(default);
+
+        Endpoints {
+            auth_url: var("SNAPGENDA_GOOGLE_AUTH_URL", defaults.auth_url),
+            token_url: var("SNAPGENDA_GOOGLE_TOKEN_URL", defaults.token_url),
+            calendar_api_url: var("SNAPGENDA_GOOGLE_API_URL", defaults.calendar_api_url)
+                .trim_end_matches('/')
+                .to_string(),
+        }
+    }
+}
+
+/// Returns an access token. A cached token is reused while it is valid. Otherwise a service
+/// account signs a new assertion, while a user token is refreshed and only when there is no
+/// token or the refresh fails the user is asked for consent.
+fn do_auth(client_args: &ClientArgs, token_cache: &TokenCache) -> Result<String, Error> {
+    let cached = token_cache.load().unwrap_or_else(|e| {
+        eprintln!(
+            "Ignoring unreadable token cache {}: {}",
+            token_cache.path().display(),
+            e
+        );
+        None
+    });
+
+    if let Some(token) = &cached {
+        if !token.is_expired(Utc::now()) {
+            return Ok(token.access_token.clone());
+        }
+    }
+
+    let token = match &client_args.credentials {
+        Credentials::User {
+            id,
+            secret,
+            manual_auth,
+        } => {
+            let client = new_oauth_client(id, secret, &client_args.endpoints)?;
+            user_token(
+                &client_args.http,
+                &client,
+                cached.and_then(|t| t.refresh_token),
+                *manual_auth,
+            )?
+        }
+        Credentials::ServiceAccount { key, subject } => service_account::request_token(
+            &client_args.http,
+            key,
+            subject.as_deref(),
+            CALENDAR_SCOPE,
+            &client_args.endpoints.token_url,
+        )?,
+    };
+    token_cache.store(&token)?;
+
+    Ok(token.access_token)
+}
+
+fn user_token(
+    http: &HttpClient,
+    client: &BasicClient,
+    refresh_token: Option<String>,
+    manual_auth: bool,
+) -> Result<CachedToken, Error> {
+    if let Some(refresh_token) = refresh_token {
+        match refresh_access_token(http, client, refresh_token) {
+            Ok(token) => return Ok(token),
+            Err(e) => eprintln!("Refreshing the access token failed: {}", e),
+        }
+    }
+
+    request_consent(http, client, manual_auth)
+}
+
+fn new_oauth_client(id: &str, secret: &str, endpoints: &Endpoints) -> Result<BasicClient, Error> {
+    let client_id = ClientId::new(id.to_string());
+    let client_secret = ClientSecret::new(secret.to_string());
+    let auth_url = AuthUrl::new(endpoints.auth_url.clone())?;
+    let token_url = TokenUrl::new(endpoints.token_url.clone())?;
+
+    Ok(BasicClient::new(
+        client_id,
+        Some(client_secret),
+        auth_url,
+        Some(token_url),
+    ))
+}
+
+fn refresh_access_token(
+    http: &HttpClient,
+    client: &BasicClient,
+    refresh_token: String,
+) -> Result<CachedToken, Error> {
+    let token_result = client
+        .exchange_refresh_token(&RefreshToken::new(refresh_token.clone()))
+        // The refresh token stays valid, repeating the request only hands out another token.
+        .request(|request| oauth_request(http, request, true))?;
+
+    // Google does not send a new refresh token on refresh, the old one stays valid.
+    Ok(cached_token(&token_result, Some(refresh_token)))
+}
+
+fn request_consent(
+    http: &HttpClient,
+    client: &BasicClient,
+    manual_auth: bool,
+) -> Result<CachedToken, Error> {
+    let listener = if manual_auth {
+        None
+    } else {
+        match LoopbackListener::bind() {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                eprintln!("Falling back to manual authorization: {}", e);
+                None
+            }
+        }
+    };
+
+    let redirect_url = match &listener {
+        Some(listener) => listener.redirect_url()?,
+        None => MANUAL_REDIRECT_URL.to_string(),
+    };
+    let client = client
+        .clone()
+        .set_redirect_uri(RedirectUrl::new(redirect_url)?);
+
+    let (auth_url, csrf_token, pkce_verifier) = authorization_url(&client);
 
     println!("Open this URL in your browser:\n{}\n", auth_url);
 
-    print!("Enter the authorization code: ");
-    io::stdout().flush()?;
-    let mut auth_code = String::new();
-    io::stdin().read_line(&mut auth_code)?;
-    let auth_code = auth_code.trim().to_string();
+    let auth_code = match listener {
+        Some(listener) => {
+            println!("Waiting for the browser to be redirected...");
+            listener.receive_code(csrf_token.secret())?
+        }
+        None => {
+            println!("After granting access the browser fails to load a 127.0.0.1 page.");
+            print!("Paste the full URL from the address bar: ");
+            io::stdout().flush()?;
+            let mut pasted = String::new();
+            io::stdin().read_line(&mut pasted)?;
+            loopback::code_from_pasted_url(&pasted, csrf_token.secret())?
+        }
+    };
+
+    exchange_code(http, &client, auth_code, pkce_verifier)
+}
 
+fn exchange_code(
+    http: &HttpClient,
+    client: &BasicClient,
+    auth_code: String,
+    pkce_verifier: PkceCodeVerifier,
+) -> Result<CachedToken, Error> {
     let token_result = client
         .exchange_code(AuthorizationCode::new(auth_code))
         .set_pkce_verifier(pkce_verifier)
-        .request(http_client)?;
+        // A code is only valid once, a retry after the first request reached Google fails
+        // with invalid_grant.
+        .request(|request| oauth_request(http, request, false))?;
+
+    Ok(cached_token(&token_result, None))
+}
+
+/// Sends a request of the oauth2 crate through the shared client, so token requests get the
+/// same timeout as the API requests. Only idempotent grants are retried. oauth2 uses an older
+/// version of the http types, they are converted by name.
+fn oauth_request(
+    http: &HttpClient,
+    request: oauth2::HttpRequest,
+    idempotent: bool,
+) -> Result<oauth2::HttpResponse, reqwest::Error> {
+    let method = reqwest::Method::from_bytes(request.method.as_str().as_bytes())
+        .expect("oauth2 sends standard methods");
+    let mut builder = http
+        .request(method, request.url.as_str())
+        .body(request.body);
+    for (name, value) in &request.headers {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    let response = if idempotent {
+        http.send_idempotent(builder)?
+    } else {
+        http.send(builder)?
+    };
+
+    let status_code = oauth2::http::StatusCode::from_u16(response.status().as_u16())
+        .expect("reqwest only returns valid status codes");
+    let mut headers = oauth2::http::HeaderMap::new();
+    for (name, value) in response.headers() {
+        if let (Ok(name), Ok(value)) = (
+            oauth2::http::HeaderName::from_bytes(name.as_str().as_bytes()),
+            oauth2::http::HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            headers.append(name, value);
+        }
+    }
+
+    Ok(oauth2::HttpResponse {
+        status_code,
+        headers,
+        body: response.bytes()?.to_vec(),
+    })
+}
+
+fn authorization_url(client: &BasicClient) -> (Url, CsrfToken, PkceCodeVerifier) {
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+    let (auth_url, csrf_token) = client
+        .authorize_url(CsrfToken::new_random)
+        .add_scope(Scope::new(CALENDAR_SCOPE.to_string()))
+        // Without offline access Google does not hand out a refresh token.
+        .add_extra_param("access_type", "offline")
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    (auth_url, csrf_token, pkce_verifier)
+}
+
+fn cached_token(token_result: &BasicTokenResponse, refresh_token: Option<String>) -> CachedToken {
+    CachedToken {
+        access_token: token_result.access_token().secret().to_string(),
+        refresh_token: token_result
+            .refresh_token()
+            .map(|t| t.secret().to_string())
+            .or(refresh_token),
+        expires_at: token_result
+            .expires_in()
+            .and_then(|d| chrono::TimeDelta::from_std(d).ok())
+            .map(|d| Utc::now() + d),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::http::{mock_server, MockResponse, RetryPolicy};
+
+    fn client() -> HttpClient {
+        HttpClient::new(RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        })
+        .unwrap()
+    }
+
+    fn date_time(s: &str) -> EventTime {
+        EventTime::DateTime(DateTime::parse_from_rfc3339(s).unwrap())
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> EventTime {
+        EventTime::Date(NaiveDate::from_ymd_opt(y, m, d).unwrap())
+    }
+
+    #[test]
+    fn test_parse_page() {
+        let body = include_str!("../tests/fixtures/google/events_list.json");
+
+        let page = parse_page(body).unwrap();
+
+        let exp = vec![
+            Event {
+                summary: Some("Weekly Planning".to_string()),
+                from: date_time("2024-09-09T10:00:00+02:00"),
+                to: date_time("2024-09-09T11:00:00+02:00"),
+                availability: Availability::Busy,
+                private: false,
+            },
+            Event {
+                summary: Some("Company Offsite".to_string()),
+                from: date(2024, 9, 11),
+                to: date(2024, 9, 13),
+                availability: Availability::Free,
+                private: false,
+            },
+            Event {
+                summary: Some(NO_TITLE.to_string()),
+                from: date_time("2024-09-12T14:30:00Z"),
+                to: date_time("2024-09-12T15:15:00Z"),
+                availability: Availability::Tentative,
+                private: false,
+            },
+            Event {
+                summary: Some("Lunch with Sam".to_string()),
+                from: date_time("2024-09-13T12:00:00+02:00"),
+                to: date_time("2024-09-13T13:00:00+02:00"),
+                availability: Availability::Busy,
+                private: true,
+            },
+        ];
+        assert_eq!(exp, page.events);
+    }
 
-    Ok(token_result.access_token().secret().to_string())
+    #[test]
+    fn test_event_to_event() {
+        let utc = |d: u32, h: u32| {
+            snapgenda::EventTime::DateTime(Utc.with_ymd_and_hms(2024, 9, d, h, 0, 0).unwrap())
+        };
+
+        let timed = Event {
+            summary: Some("Weekly Planning".to_string()),
+            from: date_time("2024-09-09T10:00:00+02:00"),
+            to: date_time("2024-09-09T11:00:00+02:00"),
+            availability: Availability::Busy,
+            private: false,
+        };
+        let exp = snapgenda::Event {
+            summary: Some("Weekly Planning".to_string()),
+            start: utc(9, 8),
+            end: utc(9, 9),
+            availability: Availability::Busy,
+            private: false,
+        };
+        assert_eq!(exp, timed.to_event());
+
+        let all_day = Event {
+            summary: Some("Company Offsite".to_string()),
+            from: date(2024, 9, 11),
+            to: date(2024, 9, 13),
+            availability: Availability::Free,
+            private: true,
+        };
+        let exp = snapgenda::Event {
+            summary: Some("Company Offsite".to_string()),
+            start: snapgenda::EventTime::Date(NaiveDate::from_ymd_opt(2024, 9, 11).unwrap()),
+            end: snapgenda::EventTime::Date(NaiveDate::from_ymd_opt(2024, 9, 13).unwrap()),
+            availability: Availability::Free,
+            private: true,
+        };
+        assert_eq!(exp, all_day.to_event());
+    }
+
+    // Stands in for Google's consent screen: once the user agreed it redirects the browser to
+    // the redirect_uri with the code and the unchanged state.
+    fn fake_authorization_server(auth_url: Url, state_override: Option<&str>) {
+        let param = |name: &str| {
+            auth_url
+                .query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_string())
+                .unwrap()
+        };
+        let redirect_uri = Url::parse(&param("redirect_uri")).unwrap();
+        let state = state_override.map(str::to_string).unwrap_or(param("state"));
+
+        let addr = format!(
+            "{}:{}",
+            redirect_uri.host_str().unwrap(),
+            redirect_uri.port().unwrap()
+        );
+        let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+        let request = format!(
+            "GET /?state={}&code=4%2Ffake-code&scope=calendar.readonly HTTP/1.1\r\nHost: {}\r\n\r\n",
+            state, addr
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        std::io::Read::read_to_string(&mut stream, &mut String::new()).unwrap();
+    }
+
+    #[test]
+    fn test_loopback_authorization() {
+        for (state_override, exp) in [
+            (None, Ok("4/fake-code".to_string())),
+            (Some("forged"), Err(loopback::Error::StateMismatch)),
+        ] {
+            let listener = LoopbackListener::bind().unwrap();
+            let client = new_oauth_client("client-id", "client-secret", &Endpoints::default())
+                .unwrap()
+                .set_redirect_uri(RedirectUrl::new(listener.redirect_url().unwrap()).unwrap());
+
+            let (auth_url, csrf_token, _) = authorization_url(&client);
+            assert!(auth_url
+                .query_pairs()
+                .any(|(k, v)| k == "access_type" && v == "offline"));
+
+            let server =
+                std::thread::spawn(move || fake_authorization_server(auth_url, state_override));
+            let code = listener.receive_code(csrf_token.secret());
+            server.join().unwrap();
+
+            assert_eq!(exp, code);
+        }
+    }
+
+    #[test]
+    fn test_exchange_code_no_retry() {
+        let (base_url, server) = mock_server(vec![MockResponse::new(503, "{}")]);
+        let endpoints = Endpoints {
+            token_url: format!("{}/token", base_url),
+            ..Endpoints::default()
+        };
+        let client = new_oauth_client("client-id", "client-secret", &endpoints).unwrap();
+        let http = HttpClient::new(RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        })
+        .unwrap();
+        let (_, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let result = exchange_code(&http, &client, "4/fake-code".to_string(), pkce_verifier);
+
+        assert!(result.is_err());
+        assert_eq!(vec!["POST /token HTTP/1.1"], server.join().unwrap());
+    }
+
+    #[test]
+    fn test_refresh_access_token_retries() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::new(503, "{}"),
+            MockResponse::new(
+                200,
+                r#"{"access_token": "new-token", "token_type": "Bearer", "expires_in": 3599}"#,
+            ),
+        ]);
+        let endpoints = Endpoints {
+            token_url: format!("{}/token", base_url),
+            ..Endpoints::default()
+        };
+        let client = new_oauth_client("client-id", "client-secret", &endpoints).unwrap();
+        let http = HttpClient::new(RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        })
+        .unwrap();
+
+        let token = refresh_access_token(&http, &client, "refresh-token".to_string()).unwrap();
+
+        assert_eq!("new-token", token.access_token);
+        assert_eq!(Some("refresh-token".to_string()), token.refresh_token);
+        assert_eq!(
+            vec!["POST /token HTTP/1.1", "POST /token HTTP/1.1"],
+            server.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_endpoints_from_vars() {
+        assert_eq!(Endpoints::default(), Endpoints::from_vars(|_| None));
+
+        let endpoints = Endpoints::from_vars(|name| match name {
+            "SNAPGENDA_GOOGLE_API_URL" => Some("http://127.0.0.1:8080/calendar/v3/".to_string()),
+            "SNAPGENDA_GOOGLE_TOKEN_URL" => Some("http://127.0.0.1:8080/token".to_string()),
+            _ => Some(String::new()),
+        });
+        assert_eq!(
+            Endpoints {
+                auth_url: AUTH_URL.to_string(),
+                token_url: "http://127.0.0.1:8080/token".to_string(),
+                calendar_api_url: "http://127.0.0.1:8080/calendar/v3".to_string(),
+            },
+            endpoints
+        );
+    }
+
+    #[test]
+    fn test_events_query() {
+        let from = Utc.with_ymd_and_hms(2024, 9, 9, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 9, 16, 0, 0, 0).unwrap();
+
+        let query = events_query(from, to, None);
+
+        assert_eq!(
+            vec![
+                ("timeMin", "2024-09-09T00:00:00+00:00".to_string()),
+                ("timeMax", "2024-09-16T00:00:00+00:00".to_string()),
+                ("singleEvents", "true".to_string()),
+                ("orderBy", "startTime".to_string()),
+                ("maxResults", "250".to_string()),
+            ],
+            query
+        );
+
+        let query = events_query(from, to, Some("page-2"));
+        assert_eq!(Some(&("pageToken", "page-2".to_string())), query.last());
+    }
+
+    #[test]
+    fn test_events_url() {
+        let test_cases = vec![
+            (
+                "https://www.googleapis.com/calendar/v3",
+                "jane.doe@example.com",
+                "https://www.googleapis.com/calendar/v3/calendars/jane.doe@example.com/events",
+            ),
+            (
+                "https://www.googleapis.com/calendar/v3",
+                "en.german#holiday@group.v.calendar.google.com",
+                "https://www.googleapis.com/calendar/v3/calendars/en.german%23holiday@group.v.calendar.google.com/events",
+            ),
+            (
+                "http://127.0.0.1:8080/calendar/v3/",
+                "team/room?1",
+                "http://127.0.0.1:8080/calendar/v3/calendars/team%2Froom%3F1/events",
+            ),
+        ];
+
+        for (api_url, calendar_id, exp) in test_cases {
+            assert_eq!(
+                exp,
+                events_url(api_url, calendar_id).unwrap().as_str(),
+                "{}",
+                calendar_id
+            );
+        }
+
+        assert!(matches!(
+            events_url("not a url", "jane.doe@example.com"),
+            Err(Error::Input(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_events_pages() {
+        let from = Utc.with_ymd_and_hms(2024, 9, 9, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 9, 16, 0, 0, 0).unwrap();
+        let (base_url, server) = mock_server(vec![
+            MockResponse::new(
+                200,
+                include_str!("../tests/fixtures/google/events_page_1.json"),
+            ),
+            MockResponse::new(
+                200,
+                include_str!("../tests/fixtures/google/events_page_2.json"),
+            ),
+        ]);
+
+        let calendar_id = "jane.doe@example.com".to_string();
+        let events: Vec<Event> = Events::new(
+            client(),
+            &base_url,
+            calendar_id,
+            "token".to_string(),
+            from,
+            to,
+        )
+        .unwrap()
+        .collect::<Result<Vec<Event>, Error>>()
+        .unwrap();
+        let requests = server.join().unwrap();
+
+        let starts: Vec<EventTime> = events.into_iter().map(|e| e.from).collect();
+        assert_eq!(
+            vec![
+                date_time("2024-09-09T09:00:00+02:00"),
+                date_time("2024-09-10T09:00:00+02:00"),
+                date_time("2024-09-11T09:00:00+02:00"),
+            ],
+            starts
+        );
+        assert_eq!(2, requests.len());
+        assert!(
+            requests[0].starts_with("GET /calendars/jane.doe@example.com/events?"),
+            "{}",
+            requests[0]
+        );
+        assert!(requests[0].contains("maxResults=250"), "{}", requests[0]);
+        assert!(!requests[0].contains("pageToken"), "{}", requests[0]);
+        assert!(
+            requests[1]
+                .contains("pageToken=CigKGjRxMXYyaDNwdDBkMGpxMW4wZjBjN2xyNmtzGAEggICA6oiZgwM%3D"),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[test]
+    fn test_list_events_forbidden() {
+        let from = Utc.with_ymd_and_hms(2024, 9, 9, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 9, 16, 0, 0, 0).unwrap();
+        let (base_url, server) =
+            mock_server(vec![MockResponse::new(403, r#"{"error": {"code": 403}}"#)]);
+
+        let calendar_id = "max@example.com".to_string();
+        let mut events = Events::new(
+            client(),
+            &base_url,
+            calendar_id,
+            "token".to_string(),
+            from,
+            to,
+        )
+        .unwrap();
+        assert!(matches!(
+            events.next(),
+            Some(Err(Error::PermissionDenied(_)))
+        ));
+        assert!(events.next().is_none());
+        server.join().unwrap();
+    }
+
+    struct ErrorFromResponseTestCase {
+        status: StatusCode,
+        retry_after: Option<&'static str>,
+        body: String,
+        exp: &'static str,
+    }
+
+    #[test]
+    fn test_error_from_response() {
+        let api_error = |code: u16, reason: &str, message: &str| {
+            format!(
+                r#"{{"error": {{"code": {}, "message": "{}", "errors": [{{"domain": "global", "reason": "{}", "message": "{}"}}]}}}}"#,
+                code, message, reason, message
+            )
+        };
+        let test_cases = vec![
+            ErrorFromResponseTestCase {
+                status: StatusCode::UNAUTHORIZED,
+                retry_after: None,
+                body: api_error(401, "authError", "Invalid Credentials"),
+                exp: "TokenExpired(\"Invalid Credentials\")",
+            },
+            ErrorFromResponseTestCase {
+                status: StatusCode::FORBIDDEN,
+                retry_after: None,
+                body: api_error(403, "forbidden", "Forbidden"),
+                exp: "PermissionDenied(\"max@example.com: Forbidden\")",
+            },
+            ErrorFromResponseTestCase {
+                status: StatusCode::FORBIDDEN,
+                retry_after: None,
+                body: api_error(403, "rateLimitExceeded", "Rate Limit Exceeded"),
+                exp: "RateLimited { retry_after: None }",
+            },
+            ErrorFromResponseTestCase {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                retry_after: Some("30"),
+                body: api_error(429, "rateLimitExceeded", "Rate Limit Exceeded"),
+                exp: "RateLimited { retry_after: Some(30s) }",
+            },
+            ErrorFromResponseTestCase {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                retry_after: Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+                body: api_error(429, "rateLimitExceeded", "Rate Limit Exceeded"),
+                exp: "RateLimited { retry_after: Some(0ns) }",
+            },
+            ErrorFromResponseTestCase {
+                status: StatusCode::NOT_FOUND,
+                retry_after: None,
+                body: api_error(404, "notFound", "Not Found"),
+                exp: "NotFound(\"max@example.com\")",
+            },
+            ErrorFromResponseTestCase {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                retry_after: None,
+                body: api_error(500, "backendError", "Backend Error"),
+                exp: "Http { status: 500, body: \"Backend Error\" }",
+            },
+            ErrorFromResponseTestCase {
+                status: StatusCode::BAD_GATEWAY,
+                retry_after: None,
+                body: "<html>Bad Gateway</html>\n".to_string(),
+                exp: "Http { status: 502, body: \"<html>Bad Gateway</html>\" }",
+            },
+        ];
+
+        for test_case in test_cases {
+            let act = error_from_response(
+                test_case.status,
+                test_case.retry_after,
+                &test_case.body,
+                "max@example.com",
+            );
+            assert_eq!(test_case.exp, format!("{:?}", act), "{}", test_case.status);
+        }
+    }
+
+    struct AvailabilityTestCase {
+        name: &'static str,
+        fields: String,
+        exp: Availability,
+    }
+
+    #[test]
+    fn test_event_availability() {
+        let case = |name, fields: &str, exp| AvailabilityTestCase {
+            name,
+            fields: fields.to_string(),
+            exp,
+        };
+        let invite = |name, response: &str, exp| AvailabilityTestCase {
+            name,
+            fields: format!(
+                r#""attendees": [{{"email": "john.roe@example.com", "responseStatus": "accepted"}}, {{"email": "jane.doe@example.com", "self": true, "responseStatus": "{}"}}]"#,
+                response
+            ),
+            exp,
+        };
+
+        let test_cases = vec![
+            case("own event", r#""eventType": "default""#, Availability::Busy),
+            case(
+                "transparent",
+                r#""transparency": "transparent""#,
+                Availability::Free,
+            ),
+            case(
+                "tentative event",
+                r#""status": "tentative""#,
+                Availability::Tentative,
+            ),
+            case(
+                "out of office",
+                r#""eventType": "outOfOffice", "transparency": "opaque""#,
+                Availability::OutOfOffice,
+            ),
+            case(
+                "focus time",
+                r#""eventType": "focusTime""#,
+                Availability::Focus,
+            ),
+            case(
+                "working location",
+                r#""eventType": "workingLocation", "transparency": "transparent""#,
+                Availability::WorkingElsewhere,
+            ),
+            case(
+                "unknown event type",
+                r#""eventType": "birthday", "transparency": "transparent""#,
+                Availability::Free,
+            ),
+            invite("accepted", "accepted", Availability::Busy),
+            invite("maybe", "tentative", Availability::Tentative),
+            invite("not answered", "needsAction", Availability::Tentative),
+            invite("declined", "declined", Availability::Free),
+        ];
+
+        for test_case in test_cases {
+            let body = format!(
+                r#"{{"items": [{{"id": "a", {}, "start": {{"date": "2024-09-10"}}, "end": {{"date": "2024-09-11"}}}}]}}"#,
+                test_case.fields
+            );
+            let page = parse_page(&body).unwrap();
+            assert_eq!(
+                test_case.exp, page.events[0].availability,
+                "{}",
+                test_case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_free_busy_request() {
+        let from = Utc.with_ymd_and_hms(2024, 9, 9, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 9, 16, 0, 0, 0).unwrap();
+        let ids = vec![
+            "jane.doe@example.com".to_string(),
+            "john.roe@example.com".to_string(),
+        ];
+
+        let body = serde_json::to_value(FreeBusyRequest::new(&ids, from, to)).unwrap();
+
+        assert_eq!(
+            serde_json::json!({
+                "timeMin": "2024-09-09T00:00:00Z",
+                "timeMax": "2024-09-16T00:00:00Z",
+                "items": [{"id": "jane.doe@example.com"}, {"id": "john.roe@example.com"}],
+            }),
+            body
+        );
+    }
+
+    #[test]
+    fn test_parse_free_busy() {
+        let body = include_str!("../tests/fixtures/google/free_busy.json");
+
+        let calendars = parse_free_busy(body).unwrap();
+
+        let busy = |from, to| Event {
+            summary: None,
+            from: date_time(from),
+            to: date_time(to),
+            availability: Availability::Busy,
+            private: false,
+        };
+        assert_eq!(
+            vec![
+                busy("2024-09-09T08:00:00Z", "2024-09-09T09:00:00Z"),
+                busy("2024-09-11T14:30:00+02:00", "2024-09-11T16:00:00+02:00"),
+            ],
+            calendars["jane.doe@example.com"].as_ref().unwrap().events
+        );
+        assert!(calendars["john.roe@example.com"]
+            .as_ref()
+            .unwrap()
+            .events
+            .is_empty());
+
+        // Only the calendars with errors fail.
+        let body = r#"{"calendars": {
+            "max@example.com": {"errors": [{"domain": "global", "reason": "notFound"}], "busy": []},
+            "ann@example.com": {"errors": [{"domain": "global", "reason": "internalError"}]},
+            "jane.doe@example.com": {"busy": []}
+        }}"#;
+        let calendars = parse_free_busy(body).unwrap();
+        assert!(matches!(
+            calendars["max@example.com"],
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            calendars["ann@example.com"],
+            Err(Error::PermissionDenied(_))
+        ));
+        assert!(calendars["jane.doe@example.com"].is_ok());
+        assert!(matches!(parse_free_busy("not json"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_events_empty() {
+        let body = include_str!("../tests/fixtures/google/events_list_empty.json");
+
+        let page = parse_page(body).unwrap();
+
+        assert!(page.events.is_empty());
+    }
+
+    #[test]
+    fn test_parse_events_invalid() {
+        let test_cases = vec![
+            r#"{"items": [{"id": "a", "status": "confirmed", "end": {"date": "2024-09-10"}}]}"#,
+            r#"{"items": [{"id": "a", "start": {}, "end": {"date": "2024-09-10"}}]}"#,
+            r#"{"items": [{"id": "a", "start": {"dateTime": "not a date"}}]}"#,
+            r#"not json"#,
+        ];
+
+        for body in test_cases {
+            let res = parse_page(body);
+            assert!(matches!(res, Err(Error::Parse(_))), "{}", body);
+        }
+    }
 }
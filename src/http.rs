@@ -0,0 +1,457 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    blocking::{Client, Request, RequestBuilder, Response},
+    header::RETRY_AFTER,
+    Method, StatusCode,
+};
+
+/// How requests to calendar providers are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, 0 disables retrying.
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled for every further one.
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff. Servers asking to wait longer than this are not retried.
+    pub max_backoff: Duration,
+    /// Time a single attempt may take, including reading the response.
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before the retry following attempt, counted from 0. The
+    /// exponential backoff is jittered into its upper half so clients started together spread
+    /// out. None when the server asks to wait longer than max_backoff.
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        match retry_after {
+            Some(retry_after) if retry_after > self.max_backoff => None,
+            Some(retry_after) => Some(retry_after),
+            None => {
+                let backoff = self
+                    .initial_backoff
+                    .saturating_mul(2_u32.saturating_pow(attempt))
+                    .min(self.max_backoff);
+                Some(backoff / 2 + backoff.mul_f64(random_fraction() / 2.0))
+            }
+        }
+    }
+}
+
+/// Reasons in the error body of a 403 that Google sends for rate limits instead of a 429.
+/// https://developers.google.com/calendar/api/guides/errors#403_rate_limit_exceeded
+pub const RATE_LIMIT_REASONS: [&str; 2] = ["rateLimitExceeded", "userRateLimitExceeded"];
+
+/// Blocking HTTP client shared by the providers. Retries requests failing with a timeout, a
+/// connection error, 429, a 5xx status or a 403 with a rate limit reason.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    policy: RetryPolicy,
+}
+
+impl HttpClient {
+    pub fn new(policy: RetryPolicy) -> Result<HttpClient, reqwest::Error> {
+        let client = Client::builder().timeout(policy.timeout).build()?;
+
+        Ok(HttpClient { client, policy })
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    /// Sends the request, only idempotent methods such as GET are retried.
+    pub fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let request = request.build()?;
+        let retry = request.method().is_idempotent();
+        self.execute(request, retry)
+    }
+
+    /// Sends a request that may be retried whatever its method, e.g. a POST that only queries.
+    pub fn send_idempotent(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        self.execute(request.build()?, true)
+    }
+
+    /// The last response is returned as is once the retries are used up, so callers see the
+    /// status of the final attempt.
+    fn execute(&self, request: Request, retry: bool) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            // Requests with a streamed body cannot be cloned and are sent only once.
+            let copy = match request.try_clone() {
+                Some(copy) if retry && attempt < self.policy.max_retries => copy,
+                _ => return self.client.execute(request),
+            };
+
+            let result = self.client.execute(copy).and_then(buffer_forbidden);
+            let backoff = match &result {
+                Ok(response) if is_retryable(response) => self
+                    .policy
+                    .backoff(attempt, retry_after(response, Utc::now())),
+                Err(e) if e.is_timeout() || e.is_connect() => self.policy.backoff(attempt, None),
+                _ => return result,
+            };
+            let Some(backoff) = backoff else {
+                return result;
+            };
+
+            thread::sleep(backoff);
+            attempt += 1;
+        }
+    }
+}
+
+fn is_retryable(response: &Response) -> bool {
+    let status = response.status();
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+        || response.extensions().get::<RateLimited>().is_some()
+}
+
+/// Marks a 403 whose body names a rate limit.
+#[derive(Clone, Copy)]
+struct RateLimited;
+
+/// Reads the body of a 403 to check it for a rate limit. Returns an equal response holding
+/// the body in memory, so the caller can still read it.
+fn buffer_forbidden(response: Response) -> Result<Response, reqwest::Error> {
+    if response.status() != StatusCode::FORBIDDEN {
+        return Ok(response);
+    }
+
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version());
+    if let Some(headers) = builder.headers_mut() {
+        headers.clone_from(response.headers());
+    }
+    let body = response.bytes()?.to_vec();
+    if is_rate_limited(&body) {
+        builder = builder.extension(RateLimited);
+    }
+    let response = builder
+        .body(body)
+        .expect("status and headers come from a valid response");
+
+    Ok(Response::from(response))
+}
+
+fn is_rate_limited(body: &[u8]) -> bool {
+    let Ok(body) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return false;
+    };
+    body["error"]["errors"].as_array().is_some_and(|errors| {
+        errors
+            .iter()
+            .any(|e| RATE_LIMIT_REASONS.contains(&e["reason"].as_str().unwrap_or_default()))
+    })
+}
+
+/// Retry-After is either a number of seconds or an HTTP date.
+fn retry_after(response: &Response, now: DateTime<Utc>) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, now)
+}
+
+pub(crate) fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.to_utc() - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+// Good enough for jitter without pulling in a random number crate, every RandomState is
+// seeded differently.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// A scripted response of mock_server.
+#[cfg(test)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(&'static str, &'static str)>,
+    pub body: &'static str,
+    /// Time to wait before answering.
+    pub delay: Duration,
+}
+
+#[cfg(test)]
+impl MockResponse {
+    pub fn new(status: u16, body: &'static str) -> MockResponse {
+        MockResponse {
+            status,
+            headers: Vec::new(),
+            body,
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: &'static str) -> MockResponse {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> MockResponse {
+        self.delay = delay;
+        self
+    }
+}
+
+/// Answers one request per connection with the next of the responses, ignoring the body.
+/// Returns the base URL and the received request lines once all responses are sent.
+#[cfg(test)]
+pub fn mock_server(responses: Vec<MockResponse>) -> (String, thread::JoinHandle<Vec<String>>) {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut header = String::new();
+            let mut content_length = 0;
+            while reader.read_line(&mut header).unwrap() > 2 {
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                header.clear();
+            }
+            // Closing the connection with an unread body resets it before the client reads
+            // the response.
+            reader.read_exact(&mut vec![0; content_length]).unwrap();
+            requests.push(request_line.trim_end().to_string());
+
+            let headers: String = response
+                .headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}\r\n", name, value))
+                .collect();
+            let raw = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                response.status,
+                response.body.len(),
+                headers,
+                response.body
+            );
+            thread::sleep(response.delay);
+            // The client is gone when it timed out.
+            let _ = stream.write_all(raw.as_bytes());
+        }
+        requests
+    });
+
+    (base_url, server)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..RetryPolicy::default()
+        };
+        let ms = Duration::from_millis;
+
+        for (attempt, min, max) in [(0, 50, 100), (1, 100, 200), (3, 400, 800), (10, 500, 1000)] {
+            for _ in 0..20 {
+                let backoff = policy.backoff(attempt, None).unwrap();
+                assert!(
+                    ms(min) <= backoff && backoff <= ms(max),
+                    "attempt {}: {:?}",
+                    attempt,
+                    backoff
+                );
+            }
+        }
+
+        assert_eq!(Some(ms(700)), policy.backoff(0, Some(ms(700))));
+        assert_eq!(None, policy.backoff(0, Some(Duration::from_secs(2))));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = Utc.with_ymd_and_hms(2024, 9, 9, 8, 0, 0).unwrap();
+
+        assert_eq!(Some(Duration::from_secs(30)), parse_retry_after(" 30", now));
+        assert_eq!(
+            Some(Duration::from_secs(90)),
+            parse_retry_after("Mon, 09 Sep 2024 08:01:30 GMT", now)
+        );
+        assert_eq!(
+            Some(Duration::ZERO),
+            parse_retry_after("Mon, 09 Sep 2024 07:59:00 GMT", now)
+        );
+        assert_eq!(None, parse_retry_after("soon", now));
+    }
+
+    #[test]
+    fn test_send_retries() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::new(503, "{}"),
+            MockResponse::new(429, "{}").with_header("Retry-After", "0"),
+            MockResponse::new(200, r#"{"items": []}"#),
+        ]);
+        let client = HttpClient::new(fast_policy(3)).unwrap();
+
+        let response = client.send(client.get(&base_url)).unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(3, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_send_retries_forbidden_rate_limit() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::new(
+                403,
+                r#"{"error": {"code": 403, "message": "Rate Limit Exceeded", "errors": [{"domain": "usageLimits", "reason": "rateLimitExceeded"}]}}"#,
+            ),
+            MockResponse::new(200, r#"{"items": []}"#),
+        ]);
+        let client = HttpClient::new(fast_policy(3)).unwrap();
+
+        let response = client.send(client.get(&base_url)).unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_send_retry_budget() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::new(500, "{}"),
+            MockResponse::new(502, "{}"),
+        ]);
+        let client = HttpClient::new(fast_policy(1)).unwrap();
+
+        let response = client.send(client.get(&base_url)).unwrap();
+
+        assert_eq!(StatusCode::BAD_GATEWAY, response.status());
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_send_no_retry() {
+        let test_cases = vec![
+            // Not idempotent.
+            (MockResponse::new(503, "{}"), "POST"),
+            // Not worth a retry.
+            (MockResponse::new(404, "{}"), "GET"),
+            (
+                MockResponse::new(
+                    403,
+                    r#"{"error": {"code": 403, "errors": [{"reason": "forbidden"}]}}"#,
+                ),
+                "GET",
+            ),
+            // Asks to wait longer than the policy allows.
+            (
+                MockResponse::new(503, "{}").with_header("Retry-After", "60"),
+                "GET",
+            ),
+        ];
+
+        for (response, method) in test_cases {
+            let status = response.status;
+            let (base_url, server) = mock_server(vec![response]);
+            let client = HttpClient::new(fast_policy(3)).unwrap();
+            let request = match method {
+                "POST" => client.post(&base_url),
+                _ => client.get(&base_url),
+            };
+
+            let response = client.send(request).unwrap();
+
+            assert_eq!(status, response.status().as_u16(), "{} {}", method, status);
+            assert_eq!(1, server.join().unwrap().len(), "{} {}", method, status);
+        }
+    }
+
+    #[test]
+    fn test_send_idempotent_post() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::new(503, "{}"),
+            MockResponse::new(200, "{}"),
+        ]);
+        let client = HttpClient::new(fast_policy(3)).unwrap();
+
+        let response = client.send_idempotent(client.post(&base_url)).unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_send_timeout() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::new(200, "{}").with_delay(Duration::from_millis(500))
+        ]);
+        let client = HttpClient::new(RetryPolicy {
+            timeout: Duration::from_millis(50),
+            ..fast_policy(0)
+        })
+        .unwrap();
+
+        let result = client.send(client.get(&base_url));
+
+        assert!(result.is_err_and(|e| e.is_timeout()));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,1133 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use snapgenda::{Availability, CalendarProvider, Event, EventTime};
+
+use crate::{
+    recurrence::{self, RRule, Until},
+    tzid::{self, Observance, RuleZone, Zone},
+};
+
+// Same as Google Calendar shows events without a title.
+const NO_TITLE: &str = "(No title)";
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Io(String),
+    Parse(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => {
+                write!(f, "ICS io error: {}", e)
+            }
+            Error::Parse(e) => {
+                write!(f, "ICS parse error: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Reads events from local iCalendar files.
+pub struct IcsProvider {
+    paths: Vec<PathBuf>,
+    /// Floating times, e.g. 09:00 without TZID, are placed in this time zone.
+    floating_tz: Tz,
+}
+
+impl IcsProvider {
+    pub fn new(paths: Vec<PathBuf>, floating_tz: Tz) -> IcsProvider {
+        IcsProvider { paths, floating_tz }
+    }
+}
+
+impl CalendarProvider for IcsProvider {
+    fn fetch_events(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+        let mut vevents: Vec<VEvent> = Vec::new();
+        for path in &self.paths {
+            for vevent in read_calendar(path)? {
+                vevents.push(vevent.with_floating_zone(self.floating_tz));
+            }
+        }
+
+        // Instances with a RECURRENCE-ID override are replaced by it, also when it is cancelled.
+        let mut overridden: Vec<(&str, EventTime)> = Vec::new();
+        for vevent in &vevents {
+            if let (Some(uid), Some(recurrence_id)) = (&vevent.uid, vevent.recurrence_id) {
+                overridden.push((uid, recurrence_id.to_event_time()?));
+            }
+        }
+
+        let mut events: Vec<Event> = Vec::new();
+        for vevent in &vevents {
+            if vevent.status == Status::Cancelled {
+                continue;
+            }
+
+            let skip: Vec<EventTime> = match (&vevent.uid, vevent.recurrence_id) {
+                (Some(uid), None) => overridden
+                    .iter()
+                    .filter(|(id, _)| id == uid)
+                    .map(|(_, t)| *t)
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            for instance in vevent.instances(to, &skip)? {
+                let event = instance.to_event()?;
+                if overlaps(&event, from, to) {
+                    events.push(event);
+                }
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+fn overlaps(event: &Event, from: DateTime<Utc>, to: DateTime<Utc>) -> bool {
+    match (event.start, event.end) {
+        // All-day events begin at midnight of the time zone they are displayed in, which is not
+        // known here. Keep every date that can touch the range, the snapshot clips the rest.
+        (EventTime::Date(start), EventTime::Date(end)) => {
+            start <= to.date_naive() && end >= from.date_naive()
+        }
+        (EventTime::DateTime(start), EventTime::DateTime(end)) => start < to && end > from,
+        _ => true,
+    }
+}
+
+fn read_calendar(path: &Path) -> Result<Vec<VEvent>, Error> {
+    let content =
+        fs::read_to_string(path).map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))?;
+
+    parse_calendar(&content).map_err(|e| match e {
+        Error::Parse(e) => Error::Parse(format!("{}: {}", path.display(), e)),
+        e => e,
+    })
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+enum Status {
+    #[default]
+    Confirmed,
+    Tentative,
+    Cancelled,
+}
+
+/// A date or date time as it can appear in DTSTART, DTEND and friends.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum DateTimeValue {
+    /// VALUE=DATE, used for all-day events.
+    Date(NaiveDate),
+    /// Ends with Z.
+    Utc(NaiveDateTime),
+    /// Local time of the TZID parameter.
+    Zoned(NaiveDateTime, Zone),
+    /// Neither Z nor TZID, the time is the same in every time zone.
+    Floating(NaiveDateTime),
+}
+
+impl DateTimeValue {
+    fn to_event_time(self) -> Result<EventTime, Error> {
+        let dt = match self {
+            DateTimeValue::Date(d) => return Ok(EventTime::Date(d)),
+            DateTimeValue::Utc(dt) => dt.and_utc(),
+            DateTimeValue::Zoned(dt, Zone::Iana(tz)) => from_local(&tz, dt)?,
+            DateTimeValue::Zoned(dt, Zone::Custom(zone)) => zone.to_utc(dt),
+            DateTimeValue::Floating(dt) => from_local(&chrono::Local, dt)?,
+        };
+
+        Ok(EventTime::DateTime(dt))
+    }
+
+    fn with_floating_zone(self, tz: Tz) -> DateTimeValue {
+        match self {
+            DateTimeValue::Floating(dt) => DateTimeValue::Zoned(dt, Zone::Iana(tz)),
+            _ => self,
+        }
+    }
+
+    /// Wall clock time, all-day values start at midnight.
+    fn local(self) -> NaiveDateTime {
+        match self {
+            // Unwrap is safe here.
+            DateTimeValue::Date(d) => d.and_hms_opt(0, 0, 0).unwrap(),
+            DateTimeValue::Utc(dt) | DateTimeValue::Zoned(dt, _) | DateTimeValue::Floating(dt) => {
+                dt
+            }
+        }
+    }
+
+    /// Same kind of value and time zone at another wall clock time.
+    fn with_local(self, dt: NaiveDateTime) -> DateTimeValue {
+        match self {
+            DateTimeValue::Date(_) => DateTimeValue::Date(dt.date()),
+            DateTimeValue::Utc(_) => DateTimeValue::Utc(dt),
+            DateTimeValue::Zoned(_, tz) => DateTimeValue::Zoned(dt, tz),
+            DateTimeValue::Floating(_) => DateTimeValue::Floating(dt),
+        }
+    }
+
+    /// Converts an instant to the wall clock time of this value's time zone.
+    fn to_local(self, dt: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            // All-day events are placed in UTC, same as in Event::to_slot.
+            DateTimeValue::Date(_) | DateTimeValue::Utc(_) => dt.naive_utc(),
+            DateTimeValue::Zoned(_, Zone::Iana(tz)) => dt.with_timezone(&tz).naive_local(),
+            DateTimeValue::Zoned(_, Zone::Custom(zone)) => zone.to_local(dt),
+            DateTimeValue::Floating(_) => dt.with_timezone(&chrono::Local).naive_local(),
+        }
+    }
+
+    fn add(self, duration: TimeDelta) -> Result<DateTimeValue, Error> {
+        let value = match self {
+            DateTimeValue::Date(d) => d
+                .checked_add_signed(TimeDelta::days(duration.num_days()))
+                .map(DateTimeValue::Date),
+            DateTimeValue::Utc(dt) => dt.checked_add_signed(duration).map(DateTimeValue::Utc),
+            DateTimeValue::Zoned(dt, tz) => dt
+                .checked_add_signed(duration)
+                .map(|dt| DateTimeValue::Zoned(dt, tz)),
+            DateTimeValue::Floating(dt) => {
+                dt.checked_add_signed(duration).map(DateTimeValue::Floating)
+            }
+        };
+
+        value.ok_or(Error::Parse(format!(
+            "{} after {:?} is out of range",
+            duration, self
+        )))
+    }
+}
+
+fn from_local<T: TimeZone>(tz: &T, dt: NaiveDateTime) -> Result<DateTime<Utc>, Error> {
+    // During a DST gap the local time does not exist, RFC 5545 says to move it forward by the
+    // length of the gap.
+    tz.from_local_datetime(&dt)
+        .earliest()
+        .or(tz
+            .from_local_datetime(&(dt + TimeDelta::hours(1)))
+            .earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or(Error::Parse(format!("invalid local time {}", dt)))
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct VEvent {
+    uid: Option<String>,
+    summary: String,
+    start: DateTimeValue,
+    end: Option<DateTimeValue>,
+    duration: Option<TimeDelta>,
+    transparent: bool,
+    /// CLASS is PRIVATE or CONFIDENTIAL.
+    private: bool,
+    /// X-MICROSOFT-CDO-BUSYSTATUS as written by Outlook and Exchange.
+    busy_status: Option<Availability>,
+    status: Status,
+    rrule: Option<RRule>,
+    rdates: Vec<DateTimeValue>,
+    exdates: Vec<DateTimeValue>,
+    /// Set when the event overrides a single instance of the recurring event with the same UID.
+    recurrence_id: Option<DateTimeValue>,
+}
+
+impl VEvent {
+    fn with_floating_zone(self, tz: Tz) -> VEvent {
+        let zoned = |v: DateTimeValue| v.with_floating_zone(tz);
+
+        VEvent {
+            start: zoned(self.start),
+            end: self.end.map(zoned),
+            rdates: self.rdates.iter().copied().map(zoned).collect(),
+            exdates: self.exdates.iter().copied().map(zoned).collect(),
+            recurrence_id: self.recurrence_id.map(zoned),
+            ..self
+        }
+    }
+
+    fn end(&self) -> Result<DateTimeValue, Error> {
+        if let Some(end) = self.end {
+            return Ok(end);
+        }
+
+        if let Some(duration) = self.duration {
+            return self.start.add(duration);
+        }
+
+        // Without DTEND and DURATION an all-day event lasts one day, a timed event is a point in
+        // time.
+        match self.start {
+            DateTimeValue::Date(_) => self.start.add(TimeDelta::days(1)),
+            _ => Ok(self.start),
+        }
+    }
+
+    /// Returns the event itself or, for recurring events, every instance starting up to to.
+    /// Instances starting at one of skip, e.g. because they are overridden, are left out.
+    fn instances(&self, to: DateTime<Utc>, skip: &[EventTime]) -> Result<Vec<VEvent>, Error> {
+        if self.rrule.is_none() && self.rdates.is_empty() {
+            return Ok(vec![self.clone()]);
+        }
+
+        // Instances are expanded in wall clock time, so they keep their local time across DST
+        // changes.
+        let length = self.end()?.local() - self.start.local();
+
+        let mut starts: Vec<DateTimeValue> = match &self.rrule {
+            Some(rrule) => {
+                let until = rrule.until.map(|until| match until {
+                    // Unwrap is safe here.
+                    Until::Date(d) => d.and_hms_opt(23, 59, 59).unwrap(),
+                    Until::Local(dt) => dt,
+                    Until::Utc(dt) => self.start.to_local(dt.and_utc()),
+                });
+
+                rrule
+                    .expand(self.start.local(), until, self.start.to_local(to))
+                    .into_iter()
+                    .map(|dt| self.start.with_local(dt))
+                    .collect()
+            }
+            None => vec![self.start],
+        };
+        starts.extend(self.rdates.iter().copied());
+
+        let mut excluded = skip.to_vec();
+        for exdate in &self.exdates {
+            excluded.push(exdate.to_event_time()?);
+        }
+
+        let mut instances: Vec<VEvent> = Vec::new();
+        for start in starts {
+            let time = start.to_event_time()?;
+            if excluded.contains(&time) {
+                continue;
+            }
+            // RDATE may repeat an instance of the RRULE.
+            excluded.push(time);
+
+            instances.push(VEvent {
+                start,
+                end: Some(start.add(length)?),
+                duration: None,
+                rrule: None,
+                rdates: Vec::new(),
+                exdates: Vec::new(),
+                ..self.clone()
+            });
+        }
+
+        Ok(instances)
+    }
+
+    /// Outlook's busy status is the most specific, then TRANSP and STATUS.
+    fn to_event(&self) -> Result<Event, Error> {
+        let availability = if let Some(busy_status) = self.busy_status {
+            busy_status
+        } else if self.transparent {
+            Availability::Free
+        } else if self.status == Status::Tentative {
+            Availability::Tentative
+        } else {
+            Availability::Busy
+        };
+
+        Ok(Event {
+            summary: Some(self.summary.clone()),
+            start: self.start.to_event_time()?,
+            end: self.end()?.to_event_time()?,
+            availability,
+            private: self.private,
+        })
+    }
+}
+
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn parse_calendar(content: &str) -> Result<Vec<VEvent>, Error> {
+    let mut vevents: Vec<Vec<Property>> = Vec::new();
+    let mut vtimezones: Vec<VTimeZone> = Vec::new();
+    // Components like VALARM can be nested into a VEVENT, their properties must not end up in the
+    // event.
+    let mut components: Vec<String> = Vec::new();
+    let mut properties: Vec<Property> = Vec::new();
+
+    for (i, line) in unfold(content).iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let prop = parse_property(line).ok_or(Error::Parse(format!(
+            "line {}: invalid content line",
+            i + 1
+        )))?;
+
+        match prop.name.as_str() {
+            "BEGIN" => {
+                let component = prop.value.to_uppercase();
+                if component == "VTIMEZONE" {
+                    vtimezones.push(VTimeZone::default());
+                }
+                components.push(component);
+            }
+            "END" => match components.pop().as_deref() {
+                Some("VEVENT") => vevents.push(std::mem::take(&mut properties)),
+                Some(kind @ ("STANDARD" | "DAYLIGHT")) => {
+                    let observance = parse_observance(kind == "DAYLIGHT", &properties);
+                    properties.clear();
+                    if let Some(vtimezone) = vtimezones.last_mut() {
+                        match observance {
+                            Ok(observance) => vtimezone.observances.push(observance),
+                            Err(e) => vtimezone.error = Some(e),
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => match components.last().map(String::as_str) {
+                Some("VEVENT" | "STANDARD" | "DAYLIGHT") => properties.push(prop),
+                Some("VTIMEZONE") if prop.name == "TZID" => {
+                    if let Some(vtimezone) = vtimezones.last_mut() {
+                        vtimezone.tzid = prop.value;
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    // VTIMEZONEs may follow the events using them.
+    let zones = Zones::new(vtimezones);
+    let mut events: Vec<VEvent> = Vec::new();
+    for properties in &vevents {
+        events.extend(parse_vevent(properties, &zones)?);
+    }
+
+    for tzid in zones.unknown.borrow().iter() {
+        eprintln!(
+            "Unknown time zone {}, reading its times as floating times",
+            tzid
+        );
+    }
+
+    Ok(events)
+}
+
+#[derive(Default)]
+struct VTimeZone {
+    tzid: String,
+    observances: Vec<Observance>,
+    /// Set when an observance could not be read, the zone is left out then.
+    error: Option<Error>,
+}
+
+/// Resolves TZID parameters by zone name first, then by the VTIMEZONEs of the file.
+struct Zones {
+    custom: HashMap<String, RuleZone>,
+    /// TZIDs that could not be resolved, in the order they were seen.
+    unknown: RefCell<Vec<String>>,
+}
+
+impl Zones {
+    fn new(vtimezones: Vec<VTimeZone>) -> Zones {
+        let custom = vtimezones
+            .into_iter()
+            .filter_map(|v| {
+                if let Some(e) = &v.error {
+                    eprintln!("Ignoring time zone {}: {}", v.tzid, e);
+                    return None;
+                }
+                Some((v.tzid, RuleZone::from_observances(&v.observances)?))
+            })
+            .collect();
+
+        Zones {
+            custom,
+            unknown: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn resolve(&self, tzid: &str) -> Option<Zone> {
+        if let Some(tz) = tzid::zone_by_name(tzid) {
+            return Some(Zone::Iana(tz));
+        }
+        if let Some(zone) = self.custom.get(tzid) {
+            return Some(Zone::Custom(*zone));
+        }
+
+        let mut unknown = self.unknown.borrow_mut();
+        if !unknown.iter().any(|t| t == tzid) {
+            unknown.push(tzid.to_string());
+        }
+        None
+    }
+}
+
+fn parse_observance(daylight: bool, properties: &[Property]) -> Result<Observance, Error> {
+    let invalid = |prop: &Property| Error::Parse(format!("invalid {} {}", prop.name, prop.value));
+    let mut start: Option<NaiveDateTime> = None;
+    let mut offset = None;
+    let mut rrule: Option<RRule> = None;
+
+    for prop in properties {
+        match prop.name.as_str() {
+            "DTSTART" => {
+                start = Some(
+                    NaiveDateTime::parse_from_str(prop.value.trim(), "%Y%m%dT%H%M%S")
+                        .map_err(|_| invalid(prop))?,
+                )
+            }
+            "TZOFFSETTO" => offset = Some(tzid::parse_offset(&prop.value).ok_or(invalid(prop))?),
+            "RRULE" => {
+                rrule = Some(RRule::parse(&prop.value).map_err(|e| Error::Parse(e.to_string()))?)
+            }
+            _ => {}
+        }
+    }
+
+    let missing = |name: &str| Error::Parse(format!("time zone observance has no {}", name));
+    Ok(Observance {
+        daylight,
+        start: start.ok_or(missing("DTSTART"))?,
+        offset: offset.ok_or(missing("TZOFFSETTO"))?,
+        rrule,
+    })
+}
+
+/// Returns None for events that are skipped because their RRULE is not supported, the other
+/// events of the file are still shown.
+fn parse_vevent(properties: &[Property], zones: &Zones) -> Result<Option<VEvent>, Error> {
+    let mut uid: Option<String> = None;
+    let mut summary: Option<String> = None;
+    let mut start: Option<DateTimeValue> = None;
+    let mut end: Option<DateTimeValue> = None;
+    let mut duration: Option<TimeDelta> = None;
+    let mut transparent = false;
+    let mut private = false;
+    let mut busy_status: Option<Availability> = None;
+    let mut status = Status::default();
+    let mut rrule: Option<Result<RRule, recurrence::Error>> = None;
+    let mut rdates: Vec<DateTimeValue> = Vec::new();
+    let mut exdates: Vec<DateTimeValue> = Vec::new();
+    let mut recurrence_id: Option<DateTimeValue> = None;
+
+    for prop in properties {
+        match prop.name.as_str() {
+            "UID" => uid = Some(prop.value.clone()),
+            "SUMMARY" => summary = Some(unescape_text(&prop.value)),
+            "DTSTART" => start = Some(parse_date_time_value(prop, &prop.value, zones)?),
+            "DTEND" => end = Some(parse_date_time_value(prop, &prop.value, zones)?),
+            "RRULE" => rrule = Some(RRule::parse(&prop.value)),
+            "RDATE" => {
+                for value in prop.value.split(',') {
+                    // Periods are reduced to their start, the length is the one of the event.
+                    let start = value.split('/').next().unwrap_or(value);
+                    rdates.push(parse_date_time_value(prop, start, zones)?);
+                }
+            }
+            "EXDATE" => {
+                for value in prop.value.split(',') {
+                    exdates.push(parse_date_time_value(prop, value, zones)?);
+                }
+            }
+            "RECURRENCE-ID" => {
+                recurrence_id = Some(parse_date_time_value(prop, &prop.value, zones)?)
+            }
+            "DURATION" => duration = Some(parse_duration(&prop.value)?),
+            "X-MICROSOFT-CDO-BUSYSTATUS" => {
+                busy_status = match prop.value.to_uppercase().as_str() {
+                    "FREE" => Some(Availability::Free),
+                    "TENTATIVE" => Some(Availability::Tentative),
+                    "BUSY" => Some(Availability::Busy),
+                    "OOF" => Some(Availability::OutOfOffice),
+                    "WORKINGELSEWHERE" => Some(Availability::WorkingElsewhere),
+                    _ => None,
+                }
+            }
+            "TRANSP" => transparent = prop.value.eq_ignore_ascii_case("TRANSPARENT"),
+            "CLASS" => {
+                private = matches!(
+                    prop.value.to_uppercase().as_str(),
+                    "PRIVATE" | "CONFIDENTIAL"
+                )
+            }
+            "STATUS" => {
+                status = match prop.value.to_uppercase().as_str() {
+                    "TENTATIVE" => Status::Tentative,
+                    "CANCELLED" => Status::Cancelled,
+                    _ => Status::Confirmed,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let start = start.ok_or(Error::Parse(format!(
+        "event {} has no DTSTART",
+        summary.as_deref().unwrap_or(NO_TITLE)
+    )))?;
+    let rrule = match rrule.transpose() {
+        Ok(rrule) => rrule,
+        Err(e) => {
+            eprintln!(
+                "Skipping event {}: {}",
+                uid.as_deref().or(summary.as_deref()).unwrap_or(NO_TITLE),
+                e
+            );
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(VEvent {
+        uid,
+        summary: summary.unwrap_or(NO_TITLE.to_string()),
+        start,
+        end,
+        duration,
+        transparent,
+        private,
+        busy_status,
+        status,
+        rrule,
+        rdates,
+        exdates,
+        recurrence_id,
+    }))
+}
+
+/// Joins lines that were folded by starting the continuation with a space or tab.
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+// NAME;PARAM=VALUE;PARAM="QUOTED:VALUE":PROPERTY VALUE
+fn parse_property(line: &str) -> Option<Property> {
+    let mut in_quotes = false;
+    let mut value_start: Option<usize> = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => {
+                value_start = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let value_start = value_start?;
+    let mut head = line[..value_start].split(';');
+    let name = head.next()?.trim().to_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    let params = head
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.trim().to_uppercase(), v.trim_matches('"').to_string()))
+        .collect();
+
+    Some(Property {
+        name,
+        params,
+        value: line[value_start + 1..].to_string(),
+    })
+}
+
+// The value is passed separately, as RDATE and EXDATE can hold a list of them.
+fn parse_date_time_value(
+    prop: &Property,
+    value: &str,
+    zones: &Zones,
+) -> Result<DateTimeValue, Error> {
+    let invalid = || Error::Parse(format!("invalid {} {}", prop.name, value));
+    let value = value.trim();
+
+    if prop.param("VALUE") == Some("DATE") || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| invalid())?;
+        return Ok(DateTimeValue::Date(date));
+    }
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        let dt = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        return Ok(DateTimeValue::Utc(dt));
+    }
+
+    let dt = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+    // Times of unknown zones are better shown in the display time zone than not at all.
+    match prop.param("TZID").and_then(|tzid| zones.resolve(tzid)) {
+        Some(zone) => Ok(DateTimeValue::Zoned(dt, zone)),
+        None => Ok(DateTimeValue::Floating(dt)),
+    }
+}
+
+// [+/-]P[nW][nD][T[nH][nM][nS]], e.g. PT1H30M or P1D.
+fn parse_duration(value: &str) -> Result<TimeDelta, Error> {
+    let invalid = || Error::Parse(format!("invalid DURATION {}", value));
+
+    let (sign, rest) = match value.trim().strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.trim().trim_start_matches('+')),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut seconds: i64 = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        if c == 'T' {
+            in_time = true;
+            continue;
+        }
+
+        let n: i64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+        let unit = match (c, in_time) {
+            ('W', false) => 7 * 24 * 3600,
+            ('D', false) => 24 * 3600,
+            ('H', true) => 3600,
+            ('M', true) => 60,
+            ('S', true) => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = n
+            .checked_mul(unit)
+            .and_then(|s| seconds.checked_add(s))
+            .ok_or_else(invalid)?;
+    }
+
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+
+    TimeDelta::try_seconds(sign * seconds).ok_or_else(invalid)
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(escaped) => out.push(escaped),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 9, d, h, min, 0).unwrap()
+    }
+
+    fn utc(d: u32, h: u32, min: u32) -> EventTime {
+        EventTime::DateTime(at(d, h, min))
+    }
+
+    fn date(d: u32) -> EventTime {
+        EventTime::Date(NaiveDate::from_ymd_opt(2024, 9, d).unwrap())
+    }
+
+    fn fixture_provider() -> IcsProvider {
+        IcsProvider::new(
+            vec![PathBuf::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/ics/week.ics"
+            ))],
+            Tz::UTC,
+        )
+    }
+
+    #[test]
+    fn test_fetch_events() {
+        let mut provider = fixture_provider();
+
+        let events = provider.fetch_events(at(9, 0, 0), at(16, 0, 0)).unwrap();
+
+        let exp = vec![
+            Event {
+                summary: Some("Weekly Planning".to_string()),
+                start: utc(9, 8, 0),
+                end: utc(9, 9, 0),
+                availability: Availability::Busy,
+                private: false,
+            },
+            Event {
+                summary: Some("Company Offsite, Day 1 & 2".to_string()),
+                start: date(11),
+                end: date(13),
+                availability: Availability::Busy,
+                private: false,
+            },
+            Event {
+                summary: Some("Standup with the team in New York".to_string()),
+                start: utc(12, 13, 0),
+                end: utc(12, 13, 15),
+                availability: Availability::Busy,
+                private: false,
+            },
+            Event {
+                summary: Some("Focus time".to_string()),
+                start: utc(12, 14, 30),
+                end: utc(12, 16, 0),
+                availability: Availability::Busy,
+                private: true,
+            },
+            Event {
+                summary: Some("Parental leave reminder".to_string()),
+                start: date(13),
+                end: date(14),
+                availability: Availability::Free,
+                private: false,
+            },
+            Event {
+                summary: Some(NO_TITLE.to_string()),
+                start: utc(13, 15, 0),
+                end: utc(13, 16, 0),
+                availability: Availability::Tentative,
+                private: false,
+            },
+        ];
+        assert_eq!(exp, events);
+    }
+
+    #[test]
+    fn test_fetch_events_previous_week() {
+        let mut provider = fixture_provider();
+
+        let events = provider.fetch_events(at(2, 0, 0), at(9, 0, 0)).unwrap();
+
+        let exp = vec![Event {
+            summary: Some("Last week's planning".to_string()),
+            start: utc(2, 8, 0),
+            end: utc(2, 9, 0),
+            availability: Availability::Busy,
+            private: false,
+        }];
+        assert_eq!(exp, events);
+    }
+
+    #[test]
+    fn test_fetch_events_recurring() {
+        let mut provider = IcsProvider::new(
+            vec![PathBuf::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/ics/recurring.ics"
+            ))],
+            Tz::UTC,
+        );
+
+        let events = provider.fetch_events(at(9, 0, 0), at(16, 0, 0)).unwrap();
+
+        let event = |summary: &str, start, end| Event {
+            summary: Some(summary.to_string()),
+            start,
+            end,
+            availability: Availability::Busy,
+            private: false,
+        };
+        let exp = vec![
+            event("Standup", utc(9, 7, 30), utc(9, 7, 45)),
+            event("Standup", utc(12, 7, 30), utc(12, 7, 45)),
+            event("Standup (moved)", utc(11, 9, 0), utc(11, 9, 15)),
+            event("1:1", utc(13, 15, 0), utc(13, 15, 30)),
+            event("Payroll", date(9), date(10)),
+        ];
+        assert_eq!(exp, events);
+    }
+
+    #[test]
+    fn test_instances_keep_local_time_across_dst() {
+        let ics = "BEGIN:VEVENT\nSUMMARY:Event\nDTSTART;TZID=Europe/Berlin:20241021T090000\nDTEND;TZID=Europe/Berlin:20241021T100000\nRRULE:FREQ=WEEKLY\nEND:VEVENT\n";
+        let vevent = parse_calendar(ics).unwrap().remove(0);
+
+        let to = Utc.with_ymd_and_hms(2024, 11, 1, 0, 0, 0).unwrap();
+        let events: Vec<Event> = vevent
+            .instances(to, &[])
+            .unwrap()
+            .iter()
+            .map(|i| i.to_event().unwrap())
+            .collect();
+
+        let at = |d: u32, h: u32| {
+            EventTime::DateTime(Utc.with_ymd_and_hms(2024, 10, d, h, 0, 0).unwrap())
+        };
+        assert_eq!(
+            vec![(at(21, 7), at(21, 8)), (at(28, 8), at(28, 9))],
+            events.iter().map(|e| (e.start, e.end)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_fetch_events_missing_file() {
+        let mut provider = IcsProvider::new(vec![PathBuf::from("does/not/exist.ics")], Tz::UTC);
+
+        let res = provider.fetch_events(at(9, 0, 0), at(16, 0, 0));
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_event_availability() {
+        let test_cases = vec![
+            ("", Availability::Busy),
+            ("TRANSP:TRANSPARENT\n", Availability::Free),
+            ("STATUS:TENTATIVE\n", Availability::Tentative),
+            (
+                "TRANSP:TRANSPARENT\nX-MICROSOFT-CDO-BUSYSTATUS:OOF\n",
+                Availability::OutOfOffice,
+            ),
+            (
+                "X-MICROSOFT-CDO-BUSYSTATUS:WORKINGELSEWHERE\n",
+                Availability::WorkingElsewhere,
+            ),
+            (
+                "STATUS:TENTATIVE\nX-MICROSOFT-CDO-BUSYSTATUS:BUSY\n",
+                Availability::Busy,
+            ),
+        ];
+
+        for (properties, exp) in test_cases {
+            let ics = format!(
+                "BEGIN:VEVENT\nDTSTART:20240909T100000Z\n{}END:VEVENT\n",
+                properties
+            );
+            let vevents = parse_calendar(&ics).unwrap();
+            assert_eq!(
+                exp,
+                vevents[0].to_event().unwrap().availability,
+                "{}",
+                properties
+            );
+        }
+    }
+
+    struct ParseCalendarTestCase {
+        name: &'static str,
+        ics: &'static str,
+        exp: Result<Vec<VEvent>, Error>,
+    }
+
+    #[test]
+    fn test_parse_calendar() {
+        let naive = |d: u32, h: u32| {
+            NaiveDate::from_ymd_opt(2024, 9, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let vevent = |start, end, duration| VEvent {
+            uid: None,
+            summary: "Event".to_string(),
+            start,
+            end,
+            duration,
+            transparent: false,
+            private: false,
+            busy_status: None,
+            status: Status::Confirmed,
+            rrule: None,
+            rdates: Vec::new(),
+            exdates: Vec::new(),
+            recurrence_id: None,
+        };
+
+        let berlin = Zone::Iana(chrono_tz::Europe::Berlin);
+        let office = RuleZone::from_observances(&[Observance {
+            daylight: false,
+            start: NaiveDate::from_ymd_opt(1601, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            offset: chrono::FixedOffset::east_opt(3 * 3600).unwrap(),
+            rrule: None,
+        }])
+        .unwrap();
+
+        let test_cases = vec![
+            ParseCalendarTestCase {
+                name: "folded lines and CRLF",
+                ics: "BEGIN:VEVENT\r\nSUMMARY:Ev\r\n ent\r\nDTSTART:20240909T100000Z\r\nEND:VEVENT\r\n",
+                exp: Ok(vec![vevent(DateTimeValue::Utc(naive(9, 10)), None, None)]),
+            },
+            ParseCalendarTestCase {
+                name: "duration",
+                ics: "BEGIN:VEVENT\nSUMMARY:Event\nDTSTART:20240909T100000\nDURATION:PT1H30M\nEND:VEVENT\n",
+                exp: Ok(vec![vevent(
+                    DateTimeValue::Floating(naive(9, 10)),
+                    None,
+                    Some(TimeDelta::minutes(90)),
+                )]),
+            },
+            ParseCalendarTestCase {
+                name: "quoted tzid",
+                ics: "BEGIN:VEVENT\nSUMMARY:Event\nDTSTART;TZID=\"Europe/Berlin\":20240909T100000\nDTEND;TZID=Europe/Berlin:20240909T110000\nEND:VEVENT\n",
+                exp: Ok(vec![vevent(
+                    DateTimeValue::Zoned(naive(9, 10), berlin),
+                    Some(DateTimeValue::Zoned(naive(9, 11), berlin)),
+                    None,
+                )]),
+            },
+            ParseCalendarTestCase {
+                name: "alarm properties are ignored",
+                ics: "BEGIN:VEVENT\nSUMMARY:Event\nDTSTART;VALUE=DATE:20240909\nBEGIN:VALARM\nSUMMARY:Alarm\nTRIGGER:-PT15M\nEND:VALARM\nEND:VEVENT\n",
+                exp: Ok(vec![vevent(
+                    DateTimeValue::Date(naive(9, 0).date()),
+                    None,
+                    None,
+                )]),
+            },
+            ParseCalendarTestCase {
+                name: "missing dtstart",
+                ics: "BEGIN:VEVENT\nSUMMARY:Event\nEND:VEVENT\n",
+                exp: Err(Error::Parse("event Event has no DTSTART".to_string())),
+            },
+            ParseCalendarTestCase {
+                name: "windows time zone",
+                ics: "BEGIN:VEVENT\nSUMMARY:Event\nDTSTART;TZID=W. Europe Standard Time:20240909T100000\nEND:VEVENT\n",
+                exp: Ok(vec![vevent(
+                    DateTimeValue::Zoned(naive(9, 10), berlin),
+                    None,
+                    None,
+                )]),
+            },
+            ParseCalendarTestCase {
+                name: "time zone of a vtimezone after the event",
+                ics: "BEGIN:VEVENT\nSUMMARY:Event\nDTSTART;TZID=Office:20240909T100000\nEND:VEVENT\nBEGIN:VTIMEZONE\nTZID:Office\nBEGIN:STANDARD\nDTSTART:16010101T000000\nTZOFFSETFROM:+0300\nTZOFFSETTO:+0300\nEND:STANDARD\nEND:VTIMEZONE\n",
+                exp: Ok(vec![vevent(
+                    DateTimeValue::Zoned(naive(9, 10), Zone::Custom(office)),
+                    None,
+                    None,
+                )]),
+            },
+            ParseCalendarTestCase {
+                name: "unknown time zone is floating",
+                ics: "BEGIN:VEVENT\nSUMMARY:Event\nDTSTART;TZID=Nowhere:20240909T100000\nEND:VEVENT\n",
+                exp: Ok(vec![vevent(DateTimeValue::Floating(naive(9, 10)), None, None)]),
+            },
+            ParseCalendarTestCase {
+                name: "event with an unsupported rule is skipped",
+                ics: "BEGIN:VEVENT\nUID:hourly\nSUMMARY:Event\nDTSTART:20240909T100000\nRRULE:FREQ=HOURLY\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Event\nDTSTART:20240909T100000\nEND:VEVENT\n",
+                exp: Ok(vec![vevent(DateTimeValue::Floating(naive(9, 10)), None, None)]),
+            },
+            ParseCalendarTestCase {
+                name: "time zone with an unsupported rule is floating",
+                ics: "BEGIN:VTIMEZONE\nTZID:Office\nBEGIN:STANDARD\nDTSTART:16010101T000000\nTZOFFSETTO:+0300\nRRULE:FREQ=HOURLY\nEND:STANDARD\nEND:VTIMEZONE\nBEGIN:VEVENT\nSUMMARY:Event\nDTSTART;TZID=Office:20240909T100000\nEND:VEVENT\n",
+                exp: Ok(vec![vevent(DateTimeValue::Floating(naive(9, 10)), None, None)]),
+            },
+            ParseCalendarTestCase {
+                name: "invalid line",
+                ics: "BEGIN:VEVENT\nno colon here\nEND:VEVENT\n",
+                exp: Err(Error::Parse("line 2: invalid content line".to_string())),
+            },
+        ];
+
+        for test_case in test_cases {
+            let act = parse_calendar(test_case.ics);
+            assert_eq!(test_case.exp, act, "{}", test_case.name);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let test_cases = vec![
+            ("PT15M", Ok(TimeDelta::minutes(15))),
+            ("PT1H30M", Ok(TimeDelta::minutes(90))),
+            ("P1D", Ok(TimeDelta::days(1))),
+            ("P1W", Ok(TimeDelta::weeks(1))),
+            ("P1DT12H", Ok(TimeDelta::hours(36))),
+            ("-PT10M", Ok(TimeDelta::minutes(-10))),
+            (
+                "PT1H30",
+                Err(Error::Parse("invalid DURATION PT1H30".to_string())),
+            ),
+            ("1H", Err(Error::Parse("invalid DURATION 1H".to_string()))),
+            (
+                "P99999999999999W",
+                Err(Error::Parse(
+                    "invalid DURATION P99999999999999W".to_string(),
+                )),
+            ),
+            (
+                "P9999999999999D",
+                Err(Error::Parse("invalid DURATION P9999999999999D".to_string())),
+            ),
+        ];
+
+        for (value, exp) in test_cases {
+            assert_eq!(exp, parse_duration(value), "{}", value);
+        }
+
+        // Valid, but far beyond the dates chrono supports.
+        let start = NaiveDate::from_ymd_opt(2024, 9, 9)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let duration = parse_duration("P99999999W").unwrap();
+        assert!(DateTimeValue::Utc(start).add(duration).is_err());
+        assert!(DateTimeValue::Date(start.date()).add(duration).is_err());
+    }
+
+    #[test]
+    fn test_unescape_text() {
+        assert_eq!(
+            "Offsite, Day 1; Berlin\nBring laptop \\ charger",
+            unescape_text("Offsite\\, Day 1\\; Berlin\\nBring laptop \\\\ charger")
+        );
+    }
+}
@@ -1,8 +1,20 @@
 use std::fmt;
 
-use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, Weekday};
+use chrono::{
+    DateTime, Datelike, Days, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
 
-#[derive(Debug, PartialEq)]
+pub mod provider;
+
+pub use provider::{CalendarProvider, Event, EventTime};
+
+/// Years weeks can be requested for. chrono cannot represent dates much further out.
+pub const MIN_YEAR: i32 = 1;
+pub const MAX_YEAR: i32 = 9999;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum WeekDay {
     Monday,
     Tuesday,
@@ -54,48 +66,163 @@ impl WeekDay {
 #[derive(Debug, PartialEq)]
 pub enum Error {
     WeekOutOfRange(String),
+    SnapshotMismatch(String),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Error::WeekOutOfRange(s) => write!(f, "Week out of range {s}"),
+            Error::SnapshotMismatch(s) => write!(f, "Snapshots do not match {s}"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
-#[derive(Debug, PartialEq)]
+/// Day a week starts on. Monday follows ISO 8601, Sunday follows the US convention where week 1
+/// is the week containing January 1st.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+impl WeekStart {
+    /// Returns the first day of week 1 of the given year.
+    fn first_week_start(&self, year: i32) -> NaiveDate {
+        // Week 1 is the week containing this day.
+        let (anchor, weekday) = match self {
+            // ISO 8601: Week 1 contains the first thursday, which is the same as containing
+            // January 4th.
+            WeekStart::Monday => (NaiveDate::from_ymd_opt(year, 1, 4).unwrap(), Weekday::Mon),
+            WeekStart::Sunday => (NaiveDate::from_ymd_opt(year, 1, 1).unwrap(), Weekday::Sun),
+        };
+
+        anchor.week(weekday).first_day()
+    }
+
+    /// Returns the number of weeks in the given year, either 52 or 53.
+    fn weeks_in_year(&self, year: i32) -> u8 {
+        let days = self.first_week_start(year + 1) - self.first_week_start(year);
+        (days.num_days() / 7) as u8
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct WeekRequest {
     year: i32,
     week_number: u8,
+    week_start: WeekStart,
 }
 
 impl WeekRequest {
+    /// Creates a request for an ISO 8601 week.
     pub fn new(week_number: u8, year: i32) -> Result<WeekRequest, Error> {
+        WeekRequest::new_with_start(week_number, year, WeekStart::Monday)
+    }
+
+    pub fn new_with_start(
+        week_number: u8,
+        year: i32,
+        week_start: WeekStart,
+    ) -> Result<WeekRequest, Error> {
+        if week_number < 1 {
+            return Err(Error::WeekOutOfRange(
+                "week cannot be lower than 1".to_string(),
+            ));
+        }
+
         if week_number > 53 {
             return Err(Error::WeekOutOfRange(
                 "week cannot be higher than 53".to_string(),
             ));
         }
 
-        let wr = WeekRequest { year, week_number };
-
-        let mon = week_start(&wr);
+        if !(MIN_YEAR..=MAX_YEAR).contains(&year) {
+            return Err(Error::WeekOutOfRange(format!(
+                "year {} is not between {} and {}",
+                year, MIN_YEAR, MAX_YEAR
+            )));
+        }
 
-        // Week is considered part of the same year, when all days are part of the same year.
-        // Atleast thats how google calendar does it. Shortcut: The sunday must be in the same
-        // year.
-        let sunday = mon.checked_add_days(Days::new(6)).unwrap();
-        if sunday.year() > year {
+        if week_number > week_start.weeks_in_year(year) {
             return Err(Error::WeekOutOfRange(format!(
                 "year {} has less than {} weeks",
                 year, week_number
             )));
         }
 
-        Ok(wr)
+        Ok(WeekRequest {
+            year,
+            week_number,
+            week_start,
+        })
+    }
+
+    /// Returns the ISO 8601 week the date is part of. The year of the week can differ from the
+    /// year of the date, e.g. 2024-12-30 is part of week 1 of 2025.
+    pub fn containing(date: NaiveDate) -> WeekRequest {
+        WeekRequest::containing_with_start(date, WeekStart::Monday)
+    }
+
+    pub fn containing_with_start(date: NaiveDate, week_start: WeekStart) -> WeekRequest {
+        let mut year = date.year();
+        if date < week_start.first_week_start(year) {
+            year -= 1;
+        } else if date >= week_start.first_week_start(year + 1) {
+            year += 1;
+        }
+
+        let days = date - week_start.first_week_start(year);
+        WeekRequest {
+            year,
+            week_number: (days.num_days() / 7 + 1) as u8,
+            week_start,
+        }
+    }
+
+    /// Returns the ISO 8601 week of today in the local time zone.
+    pub fn current() -> WeekRequest {
+        WeekRequest::containing(Local::now().date_naive())
+    }
+
+    pub fn current_with_start(week_start: WeekStart) -> WeekRequest {
+        WeekRequest::containing_with_start(Local::now().date_naive(), week_start)
+    }
+
+    /// Returns the week that is the given number of weeks before or after this one.
+    pub fn offset(&self, weeks: i64) -> Result<WeekRequest, Error> {
+        let date = TimeDelta::try_weeks(weeks)
+            .and_then(|delta| self.first_day().checked_add_signed(delta))
+            .filter(|date| (MIN_YEAR..=MAX_YEAR).contains(&date.year()))
+            .ok_or(Error::WeekOutOfRange(format!(
+                "{} weeks from week {} of {} lands outside the years {} to {}",
+                weeks, self.week_number, self.year, MIN_YEAR, MAX_YEAR
+            )))?;
+
+        Ok(WeekRequest::containing_with_start(date, self.week_start))
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn week_number(&self) -> u8 {
+        self.week_number
+    }
+
+    pub fn week_start(&self) -> WeekStart {
+        self.week_start
+    }
+
+    pub fn first_day(&self) -> NaiveDate {
+        week_start(self)
+    }
+
+    pub fn last_day(&self) -> NaiveDate {
+        week_start(self).checked_add_days(Days::new(6)).unwrap()
     }
 }
 
@@ -111,9 +238,11 @@ pub struct CalendarSnapshot {
 }
 
 impl CalendarSnapshot {
-    pub fn new(wr: WeekRequest) -> CalendarSnapshot {
+    /// Creates an empty snapshot of the week. Days start at midnight of the given time zone and
+    /// all slots are displayed in it.
+    pub fn new(wr: WeekRequest, tz: Tz) -> CalendarSnapshot {
         CalendarSnapshot {
-            week: Week::new(wr),
+            week: Week::new(wr, tz),
             multi_day_slots: Vec::new(),
         }
     }
@@ -124,13 +253,187 @@ impl CalendarSnapshot {
                 continue;
             }
 
-            day.add_slot(add_slot.slot);
+            day.add_slot(add_slot.slot.clone());
         }
     }
 
+    /// Fetches the events of the week from the provider.
+    pub fn from_provider(
+        wr: WeekRequest,
+        tz: Tz,
+        provider: &mut dyn CalendarProvider,
+    ) -> Result<CalendarSnapshot, Box<dyn std::error::Error>> {
+        let mut cs = CalendarSnapshot::new(wr, tz);
+
+        let (from, to) = cs.time_range();
+        for event in provider.fetch_events(from, to)? {
+            cs.add_event(event.to_slot(tz));
+        }
+
+        Ok(cs)
+    }
+
+    /// Returns the range of the week to query providers for, from midnight of the first day until
+    /// midnight after the last day in the time zone of the snapshot.
+    pub fn time_range(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        let from = self.week.days[0].start();
+        let to = self.week.days[6].next_day_start();
+
+        (from.with_timezone(&Utc), to.with_timezone(&Utc))
+    }
+
+    /// Adds the booked slots of a snapshot of the same week and time zone. Where both are booked
+    /// the availability with the higher priority wins, so the result is busy if anyone is busy.
+    pub fn merge(&mut self, other: &CalendarSnapshot) -> Result<(), Error> {
+        let (first, other_first) = (&self.week.days[0], &other.week.days[0]);
+        if first.date != other_first.date || first.tz != other_first.tz {
+            return Err(Error::SnapshotMismatch(format!(
+                "week of {} in {} and week of {} in {}",
+                first.date, first.tz, other_first.date, other_first.tz
+            )));
+        }
+
+        for (day, other_day) in self.week.days.iter_mut().zip(&other.week.days) {
+            for slot in &other_day.slots {
+                if slot.availability != Availability::Free {
+                    day.add_slot(slot.clone());
+                }
+            }
+        }
+        self.multi_day_slots
+            .extend(other.multi_day_slots.iter().cloned());
+
+        Ok(())
+    }
+
+    /// Returns the free intervals of at least the minimum duration within the working hours of
+    /// the days that are not excluded. Everything but free time and working elsewhere counts as
+    /// booked, multi-day slots included, and is extended by the buffer on both sides.
+    pub fn free_slots(&self, query: &FreeSlotQuery) -> Vec<Slot> {
+        let mut out: Vec<Slot> = Vec::new();
+
+        for day in &self.week.days {
+            if query.excluded_days.contains(&day.week_day) {
+                continue;
+            }
+
+            let (from, to) = query.working_hours;
+            let day_from = day.at(from);
+            let day_to = if to == NaiveTime::MIN {
+                day.next_day_start()
+            } else {
+                day.at(to)
+            };
+
+            let mut booked: Vec<(DateTime<Tz>, DateTime<Tz>)> = day
+                .slots
+                .iter()
+                .chain(&self.multi_day_slots)
+                .filter(|s| !s.availability.is_available())
+                .map(|s| {
+                    // Day slots end a second before midnight.
+                    let to = if s.to == day.end() {
+                        day.next_day_start()
+                    } else {
+                        s.to
+                    };
+                    (s.from - query.buffer, to + query.buffer)
+                })
+                .collect();
+            booked.sort_by_key(|(from, _)| *from);
+
+            let mut free_from = day_from;
+            for (booked_from, booked_to) in booked {
+                let free = Slot::new(free_from, booked_from.min(day_to));
+                if free.to - free.from >= query.min_duration && free.from < free.to {
+                    out.push(free);
+                }
+                free_from = free_from.max(booked_to);
+            }
+
+            let free = Slot::new(free_from, day_to);
+            if free.to - free.from >= query.min_duration && free.from < free.to {
+                out.push(free);
+            }
+        }
+
+        out
+    }
+
     pub fn add_multi_day_slot(&mut self, s: Slot) {
         self.multi_day_slots.push(s)
     }
+
+    /// Adds an event as fetched from a provider. The event is clipped to the week. Events that
+    /// last a day or longer (e.g. all-day events) become multi-day slots, shorter events are
+    /// split at midnight into one slot per day.
+    pub fn add_event(&mut self, event: Slot) {
+        let week_start = self.week.days[0].start();
+        let week_end = self.week.days[6].next_day_start();
+
+        let from = event.from.max(week_start);
+        let to = event.to.min(week_end);
+        if from >= to {
+            return;
+        }
+
+        // Compared in wall clock time, an all-day event on a day with a DST change lasts 23 or
+        // 25 hours.
+        if event.to.naive_local() - event.from.naive_local() >= TimeDelta::days(1) {
+            self.add_multi_day_slot(Slot { from, to, ..event });
+            return;
+        }
+
+        let mut add_slots: Vec<AddSlot> = Vec::new();
+        for day in &self.week.days {
+            let day_from = from.max(day.start());
+            let day_to = to.min(day.end());
+            if day_from >= day_to {
+                continue;
+            }
+
+            add_slots.push(AddSlot {
+                week_day: day.week_day,
+                slot: Slot {
+                    from: day_from,
+                    to: day_to,
+                    ..event.clone()
+                },
+            });
+        }
+
+        for add_slot in add_slots {
+            self.add_slot(add_slot);
+        }
+    }
+}
+
+/// What CalendarSnapshot::free_slots looks for.
+#[derive(Debug, Clone)]
+pub struct FreeSlotQuery {
+    /// Wall clock times a day is searched from and until. An end of 00:00 is midnight at the end
+    /// of the day.
+    pub working_hours: (NaiveTime, NaiveTime),
+    /// Shorter free intervals are left out.
+    pub min_duration: TimeDelta,
+    /// Time kept free before and after every booked slot.
+    pub buffer: TimeDelta,
+    pub excluded_days: Vec<WeekDay>,
+}
+
+impl Default for FreeSlotQuery {
+    fn default() -> Self {
+        FreeSlotQuery {
+            // Unwrap is safe here.
+            working_hours: (
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            ),
+            min_duration: TimeDelta::minutes(30),
+            buffer: TimeDelta::zero(),
+            excluded_days: vec![WeekDay::Saturday, WeekDay::Sunday],
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -139,27 +442,20 @@ pub struct Week {
 }
 
 impl Week {
-    pub fn new(wr: WeekRequest) -> Week {
-        let mon = week_start(&wr);
+    pub fn new(wr: WeekRequest, tz: Tz) -> Week {
+        let first = week_start(&wr);
+        let day = |n: u64| Day::new(first.checked_add_days(Days::new(n)).unwrap(), tz);
         Week {
-            days: [
-                Day::new(mon),
-                Day::new(mon.checked_add_days(Days::new(1)).unwrap()),
-                Day::new(mon.checked_add_days(Days::new(2)).unwrap()),
-                Day::new(mon.checked_add_days(Days::new(3)).unwrap()),
-                Day::new(mon.checked_add_days(Days::new(4)).unwrap()),
-                Day::new(mon.checked_add_days(Days::new(5)).unwrap()),
-                Day::new(mon.checked_add_days(Days::new(6)).unwrap()),
-            ],
+            days: [day(0), day(1), day(2), day(3), day(4), day(5), day(6)],
         }
     }
 }
 
 fn week_start(wr: &WeekRequest) -> NaiveDate {
-    let start_year = NaiveDate::from_ymd_opt(wr.year, 1, 1).unwrap();
+    let first_week = wr.week_start.first_week_start(wr.year);
     let week_multiplicator = wr.week_number - 1;
     let days_until_week = (week_multiplicator as u64) * 7;
-    start_year
+    first_week
         .checked_add_days(Days::new(days_until_week))
         .unwrap()
 }
@@ -167,72 +463,209 @@ fn week_start(wr: &WeekRequest) -> NaiveDate {
 #[derive(Debug)]
 pub struct Day {
     week_day: WeekDay,
+    date: NaiveDate,
+    tz: Tz,
     pub slots: Vec<Slot>,
 }
 
 impl Day {
-    pub fn new(d: NaiveDate) -> Day {
+    /// Creates a free day. Because of DST changes a day can last 23 or 25 hours.
+    pub fn new(d: NaiveDate, tz: Tz) -> Day {
         let week_day = WeekDay::from(d.weekday());
-        // Unwrap is safe here.
-        let start = d.and_hms_opt(0, 0, 0).unwrap();
-        let end = d.and_hms_opt(23, 59, 59).unwrap();
-
-        Day {
+        let mut day = Day {
             week_day,
-            slots: vec![Slot::new(start, end)],
-        }
+            date: d,
+            tz,
+            slots: Vec::new(),
+        };
+        day.slots.push(Slot::new(day.start(), day.end()));
+
+        day
     }
 
+    pub fn week_day(&self) -> WeekDay {
+        self.week_day
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    pub fn start(&self) -> DateTime<Tz> {
+        start_of_day(&self.tz, self.date)
+    }
+
+    pub fn end(&self) -> DateTime<Tz> {
+        self.next_day_start() - TimeDelta::seconds(1)
+    }
+
+    pub fn length(&self) -> TimeDelta {
+        self.next_day_start() - self.start()
+    }
+
+    /// Returns the wall clock time on this day. A time skipped by a DST change is moved past
+    /// the gap.
+    pub fn at(&self, time: NaiveTime) -> DateTime<Tz> {
+        resolve_local(&self.tz, self.date.and_time(time))
+    }
+
+    fn next_day_start(&self) -> DateTime<Tz> {
+        // Unwrap is safe here.
+        start_of_day(&self.tz, self.date.succ_opt().unwrap())
+    }
+
+    /// Inserts a slot of arbitrary length into the day. Where it overlaps existing slots the
+    /// slot whose availability has the higher priority wins, on a tie the existing one. Afterwards
+    /// the slots still cover the whole day without gaps or overlaps.
     fn add_slot(&mut self, new_slot: Slot) {
+        let from = new_slot.from.max(self.start());
+        let to = new_slot.to.min(self.end());
+        if from >= to {
+            return;
+        }
+
         let mut new_slots: Vec<Slot> = Vec::new();
 
         for slot in &self.slots {
-            if new_slot.from >= slot.from && new_slot.to <= slot.to {
-                let before = Slot {
-                    from: slot.from,
-                    to: new_slot.from.clone(),
-                    availability: slot.availability,
-                };
-                new_slots.push(before);
-                let after = Slot {
-                    from: new_slot.to.clone(),
-                    to: slot.to,
-                    availability: slot.availability.clone(),
-                };
-                new_slots.push(new_slot);
-                new_slots.push(after);
+            if slot.to <= from || slot.from >= to {
+                new_slots.push(slot.clone());
                 continue;
             }
 
-            new_slots.push(*slot);
+            let overlap_from = slot.from.max(from);
+            let overlap_to = slot.to.min(to);
+            let winner = if new_slot.availability.priority() > slot.availability.priority() {
+                &new_slot
+            } else {
+                slot
+            };
+
+            new_slots.push(Slot {
+                to: overlap_from,
+                ..slot.clone()
+            });
+            new_slots.push(Slot {
+                from: overlap_from,
+                to: overlap_to,
+                ..winner.clone()
+            });
+            new_slots.push(Slot {
+                from: overlap_to,
+                ..slot.clone()
+            });
         }
 
-        println!("{:?}", new_slots);
-        self.slots = new_slots;
+        self.slots = normalize_slots(new_slots);
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Returns midnight of the date in the time zone. Where a DST change skips midnight the day starts
+/// with the first hour that exists.
+pub(crate) fn start_of_day(tz: &Tz, date: NaiveDate) -> DateTime<Tz> {
+    // Unwrap is safe here.
+    resolve_local(tz, date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+// The earlier of repeated times, skipped times move forward by up to two hours.
+fn resolve_local(tz: &Tz, dt: NaiveDateTime) -> DateTime<Tz> {
+    (0..=2)
+        .find_map(|h| {
+            tz.from_local_datetime(&(dt + TimeDelta::hours(h)))
+                .earliest()
+        })
+        .unwrap_or_else(|| tz.from_utc_datetime(&dt))
+}
+
+/// Drops zero-length slots and merges neighbouring slots of the same event or availability.
+fn normalize_slots(slots: Vec<Slot>) -> Vec<Slot> {
+    let mut out: Vec<Slot> = Vec::new();
+
+    for slot in slots {
+        if slot.from >= slot.to {
+            continue;
+        }
+
+        if let Some(last) = out.last_mut() {
+            if last.to == slot.from
+                && last.availability == slot.availability
+                && last.summary == slot.summary
+                && last.private == slot.private
+            {
+                last.to = slot.to;
+                continue;
+            }
+        }
+
+        out.push(slot);
+    }
+
+    out
+}
+
+#[derive(Debug, Clone)]
 pub struct Slot {
-    pub from: NaiveDateTime,
-    pub to: NaiveDateTime,
+    pub from: DateTime<Tz>,
+    pub to: DateTime<Tz>,
     pub availability: Availability,
+    /// Title of the event the slot is booked for, None for time without an event.
+    pub summary: Option<String>,
+    /// Marked private or confidential in the provider, the summary must not be shown.
+    pub private: bool,
 }
 
 impl Slot {
-    pub fn new(from: NaiveDateTime, to: NaiveDateTime) -> Slot {
+    pub fn new(from: DateTime<Tz>, to: DateTime<Tz>) -> Slot {
         Slot {
             availability: Availability::Free,
             from,
             to,
+            summary: None,
+            private: false,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Availability {
     Busy,
     Free,
+    /// Invited but not accepted yet, or accepted as maybe.
+    Tentative,
+    OutOfOffice,
+    /// Booked to get work done, can be moved if needed.
+    Focus,
+    /// Available, but not at the usual place.
+    WorkingElsewhere,
+}
+
+impl Availability {
+    /// Where slots overlap the higher priority wins: out of office, busy, focus, tentative,
+    /// working elsewhere and free. Being away beats any meeting, and a firm meeting beats one
+    /// that can still be moved or declined.
+    fn priority(&self) -> u8 {
+        match self {
+            Availability::Free => 0,
+            Availability::WorkingElsewhere => 1,
+            Availability::Tentative => 2,
+            Availability::Focus => 3,
+            Availability::Busy => 4,
+            Availability::OutOfOffice => 5,
+        }
+    }
+
+    /// Whether a meeting can be scheduled into the slot.
+    pub fn is_available(&self) -> bool {
+        matches!(self, Availability::Free | Availability::WorkingElsewhere)
+    }
+
+    /// Returns the availability that wins when two slots overlap.
+    pub fn max_priority(self, other: Availability) -> Availability {
+        if other.priority() > self.priority() {
+            other
+        } else {
+            self
+        }
+    }
 }
 
 impl fmt::Display for Availability {
@@ -240,6 +673,10 @@ impl fmt::Display for Availability {
         let s = match self {
             Availability::Free => "Free",
             Availability::Busy => "Busy",
+            Availability::Tentative => "Tentative",
+            Availability::OutOfOffice => "Out of office",
+            Availability::Focus => "Focus",
+            Availability::WorkingElsewhere => "Working elsewhere",
         };
         write!(f, "{}", s)
     }
@@ -247,8 +684,14 @@ impl fmt::Display for Availability {
 
 #[cfg(test)]
 mod tests {
+    use chrono::Timelike;
+
     use super::*;
 
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
     struct WeekStartTestCase {
         wr: WeekRequest,
         expected: NaiveDate,
@@ -256,49 +699,112 @@ mod tests {
 
     #[test]
     fn test_week_start() {
+        let iso = |week_number, year| WeekRequest {
+            year,
+            week_number,
+            week_start: WeekStart::Monday,
+        };
+        let us = |week_number, year| WeekRequest {
+            year,
+            week_number,
+            week_start: WeekStart::Sunday,
+        };
+
         let test_cases = vec![
             WeekStartTestCase {
-                wr: WeekRequest {
-                    year: 2024,
-                    week_number: 1,
-                },
-                expected: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                wr: iso(1, 2024),
+                expected: date(2024, 1, 1),
             },
             WeekStartTestCase {
-                wr: WeekRequest {
-                    year: 2024,
-                    week_number: 50,
-                },
-                expected: NaiveDate::from_ymd_opt(2024, 12, 9).unwrap(),
+                wr: iso(50, 2024),
+                expected: date(2024, 12, 9),
+            },
+            WeekStartTestCase {
+                wr: iso(1, 2025),
+                expected: date(2024, 12, 30),
+            },
+            WeekStartTestCase {
+                wr: iso(1, 2021),
+                expected: date(2021, 1, 4),
+            },
+            WeekStartTestCase {
+                wr: iso(53, 2020),
+                expected: date(2020, 12, 28),
+            },
+            WeekStartTestCase {
+                wr: iso(1, 2016),
+                expected: date(2016, 1, 4),
+            },
+            WeekStartTestCase {
+                wr: iso(1, 2015),
+                expected: date(2014, 12, 29),
+            },
+            WeekStartTestCase {
+                wr: iso(53, 2026),
+                expected: date(2026, 12, 28),
+            },
+            WeekStartTestCase {
+                wr: us(1, 2024),
+                expected: date(2023, 12, 31),
+            },
+            WeekStartTestCase {
+                wr: us(1, 2023),
+                expected: date(2023, 1, 1),
+            },
+            WeekStartTestCase {
+                wr: us(53, 2022),
+                expected: date(2022, 12, 25),
             },
         ];
 
         for test_case in test_cases {
             let act_day = week_start(&test_case.wr);
-            assert_eq!(test_case.expected, act_day);
+            assert_eq!(test_case.expected, act_day, "{:?}", test_case.wr);
         }
     }
 
     struct WeekRequestTestCase {
         year: i32,
         week_number: u8,
+        week_start: WeekStart,
         exp: Result<WeekRequest, Error>,
     }
 
     #[test]
     fn test_week_request() {
+        let ok = |week_number, year, week_start| {
+            Ok(WeekRequest {
+                year,
+                week_number,
+                week_start,
+            })
+        };
+        let less_weeks = |week_number, year| {
+            Err(Error::WeekOutOfRange(format!(
+                "year {} has less than {} weeks",
+                year, week_number
+            )))
+        };
+
         let test_cases = vec![
             WeekRequestTestCase {
                 year: 2024,
                 week_number: 1,
-                exp: Ok(WeekRequest {
-                    year: 2024,
-                    week_number: 1,
-                }),
+                week_start: WeekStart::Monday,
+                exp: ok(1, 2024, WeekStart::Monday),
+            },
+            WeekRequestTestCase {
+                year: 2024,
+                week_number: 0,
+                week_start: WeekStart::Monday,
+                exp: Err(Error::WeekOutOfRange(
+                    "week cannot be lower than 1".to_string(),
+                )),
             },
             WeekRequestTestCase {
                 year: 2024,
                 week_number: 54,
+                week_start: WeekStart::Monday,
                 exp: Err(Error::WeekOutOfRange(
                     "week cannot be higher than 53".to_string(),
                 )),
@@ -306,23 +812,757 @@ mod tests {
             WeekRequestTestCase {
                 year: 2024,
                 week_number: 53,
-                exp: Err(Error::WeekOutOfRange(
-                    "year 2024 has less than 53 weeks".to_string(),
-                )),
+                week_start: WeekStart::Monday,
+                exp: less_weeks(53, 2024),
             },
             WeekRequestTestCase {
                 year: 2024,
                 week_number: 52,
-                exp: Ok(WeekRequest {
-                    year: 2024,
-                    week_number: 52,
-                }),
+                week_start: WeekStart::Monday,
+                exp: ok(52, 2024, WeekStart::Monday),
+            },
+            WeekRequestTestCase {
+                year: 2020,
+                week_number: 53,
+                week_start: WeekStart::Monday,
+                exp: ok(53, 2020, WeekStart::Monday),
+            },
+            WeekRequestTestCase {
+                year: 2026,
+                week_number: 53,
+                week_start: WeekStart::Monday,
+                exp: ok(53, 2026, WeekStart::Monday),
+            },
+            WeekRequestTestCase {
+                year: 2025,
+                week_number: 53,
+                week_start: WeekStart::Monday,
+                exp: less_weeks(53, 2025),
+            },
+            WeekRequestTestCase {
+                year: 2022,
+                week_number: 53,
+                week_start: WeekStart::Sunday,
+                exp: ok(53, 2022, WeekStart::Sunday),
+            },
+            WeekRequestTestCase {
+                year: 2024,
+                week_number: 53,
+                week_start: WeekStart::Sunday,
+                exp: less_weeks(53, 2024),
             },
         ];
 
         for test_case in test_cases {
-            let wr_act = WeekRequest::new(test_case.week_number, test_case.year);
+            let wr_act = WeekRequest::new_with_start(
+                test_case.week_number,
+                test_case.year,
+                test_case.week_start,
+            );
             assert_eq!(test_case.exp, wr_act);
         }
     }
+
+    #[test]
+    fn test_week_request_matches_iso_week() {
+        let mut d = date(1990, 1, 1);
+        while d < date(2040, 1, 1) {
+            let wr = WeekRequest::containing(d);
+            let iso_week = d.iso_week();
+
+            assert_eq!(iso_week.year(), wr.year(), "{}", d);
+            assert_eq!(iso_week.week() as u8, wr.week_number(), "{}", d);
+            assert_eq!(Weekday::Mon, wr.first_day().weekday(), "{}", d);
+            assert!(wr.first_day() <= d && d <= wr.last_day(), "{}", d);
+            assert_eq!(Ok(wr), WeekRequest::new(wr.week_number(), wr.year()));
+
+            d = d.succ_opt().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_week_request_sunday_start() {
+        let mut d = date(1990, 1, 1);
+        while d < date(2040, 1, 1) {
+            let wr = WeekRequest::containing_with_start(d, WeekStart::Sunday);
+
+            assert_eq!(Weekday::Sun, wr.first_day().weekday(), "{}", d);
+            assert!(wr.first_day() <= d && d <= wr.last_day(), "{}", d);
+            // The week containing January 1st is always week 1.
+            let jan_first = date(wr.last_day().year(), 1, 1);
+            if wr.first_day() <= jan_first && jan_first <= wr.last_day() {
+                assert_eq!(1, wr.week_number(), "{}", d);
+            }
+            assert_eq!(
+                Ok(wr),
+                WeekRequest::new_with_start(wr.week_number(), wr.year(), WeekStart::Sunday)
+            );
+
+            d = d.succ_opt().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_week_request_offset() {
+        let wr = WeekRequest::new(52, 2024).unwrap();
+
+        assert_eq!(WeekRequest::new(1, 2025).unwrap(), wr.offset(1).unwrap());
+        assert_eq!(WeekRequest::new(50, 2024).unwrap(), wr.offset(-2).unwrap());
+        assert_eq!(
+            WeekRequest::new(53, 2020).unwrap(),
+            wr.offset(-52 * 4).unwrap()
+        );
+        assert_eq!(wr, wr.offset(0).unwrap());
+        for weeks in [999_999_999_999, i64::MIN, 52 * 10_000] {
+            assert!(
+                matches!(wr.offset(weeks), Err(Error::WeekOutOfRange(_))),
+                "{}",
+                weeks
+            );
+        }
+    }
+
+    fn dt(m: u32, d: u32, h: u32, min: u32) -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2024, m, d, h, min, 0).unwrap()
+    }
+
+    fn busy(from: DateTime<Tz>, to: DateTime<Tz>) -> Slot {
+        Slot {
+            availability: Availability::Busy,
+            ..Slot::new(from, to)
+        }
+    }
+
+    fn busy_ranges(cs: &CalendarSnapshot) -> Vec<(WeekDay, DateTime<Tz>, DateTime<Tz>)> {
+        let mut out = Vec::new();
+        for day in &cs.week.days {
+            for slot in &day.slots {
+                if let Availability::Busy = slot.availability {
+                    out.push((day.week_day, slot.from, slot.to));
+                }
+            }
+        }
+        out
+    }
+
+    struct AddEventTestCase {
+        name: &'static str,
+        event: Slot,
+        exp_slots: Vec<(WeekDay, DateTime<Tz>, DateTime<Tz>)>,
+        exp_multi_day: Vec<(DateTime<Tz>, DateTime<Tz>)>,
+    }
+
+    #[test]
+    fn test_add_event() {
+        // Week 37 of 2024 is 2024-09-09 (Mon) until 2024-09-15 (Sun).
+        let wr = WeekRequest::new(37, 2024).unwrap();
+        let end_of_day = |d: u32| dt(9, d, 23, 59) + TimeDelta::seconds(59);
+
+        let test_cases = vec![
+            AddEventTestCase {
+                name: "single day",
+                event: busy(dt(9, 10, 10, 0), dt(9, 10, 11, 30)),
+                exp_slots: vec![(WeekDay::Tuesday, dt(9, 10, 10, 0), dt(9, 10, 11, 30))],
+                exp_multi_day: vec![],
+            },
+            AddEventTestCase {
+                name: "crosses midnight",
+                event: busy(dt(9, 10, 22, 0), dt(9, 11, 2, 0)),
+                exp_slots: vec![
+                    (WeekDay::Tuesday, dt(9, 10, 22, 0), end_of_day(10)),
+                    (WeekDay::Wednesday, dt(9, 11, 0, 0), dt(9, 11, 2, 0)),
+                ],
+                exp_multi_day: vec![],
+            },
+            AddEventTestCase {
+                name: "ends at midnight",
+                event: busy(dt(9, 12, 20, 0), dt(9, 13, 0, 0)),
+                exp_slots: vec![(WeekDay::Thursday, dt(9, 12, 20, 0), end_of_day(12))],
+                exp_multi_day: vec![],
+            },
+            AddEventTestCase {
+                name: "all day",
+                event: busy(dt(9, 11, 0, 0), dt(9, 12, 0, 0)),
+                exp_slots: vec![],
+                exp_multi_day: vec![(dt(9, 11, 0, 0), dt(9, 12, 0, 0))],
+            },
+            AddEventTestCase {
+                name: "multi day clipped at week start",
+                event: busy(dt(9, 5, 0, 0), dt(9, 11, 0, 0)),
+                exp_slots: vec![],
+                exp_multi_day: vec![(dt(9, 9, 0, 0), dt(9, 11, 0, 0))],
+            },
+            AddEventTestCase {
+                name: "multi day clipped at week end",
+                event: busy(dt(9, 14, 9, 0), dt(9, 20, 17, 0)),
+                exp_slots: vec![],
+                exp_multi_day: vec![(dt(9, 14, 9, 0), dt(9, 16, 0, 0))],
+            },
+            AddEventTestCase {
+                name: "starts before week",
+                event: busy(dt(9, 8, 23, 0), dt(9, 9, 1, 0)),
+                exp_slots: vec![(WeekDay::Monday, dt(9, 9, 0, 0), dt(9, 9, 1, 0))],
+                exp_multi_day: vec![],
+            },
+            AddEventTestCase {
+                name: "ends after week",
+                event: busy(dt(9, 15, 23, 0), dt(9, 16, 1, 0)),
+                exp_slots: vec![(WeekDay::Sunday, dt(9, 15, 23, 0), end_of_day(15))],
+                exp_multi_day: vec![],
+            },
+            AddEventTestCase {
+                name: "outside of week",
+                event: busy(dt(9, 2, 10, 0), dt(9, 2, 11, 0)),
+                exp_slots: vec![],
+                exp_multi_day: vec![],
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut cs = CalendarSnapshot::new(wr, Tz::UTC);
+            cs.add_event(test_case.event);
+
+            assert_eq!(test_case.exp_slots, busy_ranges(&cs), "{}", test_case.name);
+            let multi_day: Vec<(DateTime<Tz>, DateTime<Tz>)> =
+                cs.multi_day_slots.iter().map(|s| (s.from, s.to)).collect();
+            assert_eq!(test_case.exp_multi_day, multi_day, "{}", test_case.name);
+        }
+    }
+
+    #[test]
+    fn test_add_event_summary() {
+        let wr = WeekRequest::new(37, 2024).unwrap();
+        let event = |h: u32, summary: &str| Slot {
+            summary: Some(summary.to_string()),
+            ..busy(dt(9, 10, h, 0), dt(9, 10, h + 2, 0))
+        };
+
+        let mut cs = CalendarSnapshot::new(wr, Tz::UTC);
+        cs.add_event(event(10, "Planning"));
+        cs.add_event(event(11, "Review"));
+        cs.add_event(event(14, "Review"));
+        cs.add_event(event(15, "Review"));
+
+        // Overlaps keep the event added first, neighbouring slots of the same event are merged.
+        let act: Vec<(u32, u32, Option<String>)> = cs.week.days[1]
+            .slots
+            .iter()
+            .map(|s| (s.from.hour(), s.to.hour(), s.summary.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (0, 10, None),
+                (10, 12, Some("Planning".to_string())),
+                (12, 13, Some("Review".to_string())),
+                (13, 14, None),
+                (14, 17, Some("Review".to_string())),
+                (17, 23, None),
+            ],
+            act
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let wr = WeekRequest::new(37, 2024).unwrap();
+        let snapshot = |events: Vec<Slot>| {
+            let mut cs = CalendarSnapshot::new(wr, Tz::UTC);
+            for event in events {
+                cs.add_event(event);
+            }
+            cs
+        };
+
+        let mut cs = snapshot(vec![
+            busy(dt(9, 10, 9, 0), dt(9, 10, 10, 0)),
+            Slot {
+                availability: Availability::Tentative,
+                ..Slot::new(dt(9, 10, 13, 0), dt(9, 10, 15, 0))
+            },
+        ]);
+        let other = snapshot(vec![
+            busy(dt(9, 10, 9, 30), dt(9, 10, 11, 0)),
+            busy(dt(9, 10, 14, 0), dt(9, 10, 16, 0)),
+            busy(dt(9, 11, 0, 0), dt(9, 12, 0, 0)),
+        ]);
+        cs.merge(&other).unwrap();
+
+        // Busy wins over tentative, the free slots of the other snapshot change nothing.
+        let act: Vec<(u32, u32, Availability)> = cs.week.days[1]
+            .slots
+            .iter()
+            .filter(|s| s.availability != Availability::Free)
+            .map(|s| (s.from.hour(), s.to.hour(), s.availability))
+            .collect();
+        assert_eq!(
+            vec![
+                (9, 11, Availability::Busy),
+                (13, 14, Availability::Tentative),
+                (14, 16, Availability::Busy),
+            ],
+            act
+        );
+        assert_eq!(1, cs.multi_day_slots.len());
+
+        let next_week = CalendarSnapshot::new(wr.offset(1).unwrap(), Tz::UTC);
+        assert!(matches!(
+            cs.merge(&next_week),
+            Err(Error::SnapshotMismatch(_))
+        ));
+        let berlin = CalendarSnapshot::new(wr, chrono_tz::Europe::Berlin);
+        assert!(matches!(cs.merge(&berlin), Err(Error::SnapshotMismatch(_))));
+    }
+
+    struct FreeSlotsTestCase {
+        name: &'static str,
+        events: Vec<Slot>,
+        query: FreeSlotQuery,
+        exp: Vec<(DateTime<Tz>, DateTime<Tz>)>,
+    }
+
+    #[test]
+    fn test_free_slots() {
+        let wr = WeekRequest::new(37, 2024).unwrap();
+        let t = |h: u32, min: u32| NaiveTime::from_hms_opt(h, min, 0).unwrap();
+        let weekdays_except = |days: &[WeekDay]| FreeSlotQuery {
+            excluded_days: WeekDay::week_days()
+                .into_iter()
+                .filter(|d| !days.contains(d))
+                .collect(),
+            ..FreeSlotQuery::default()
+        };
+        let tuesday = weekdays_except(&[WeekDay::Tuesday]);
+
+        let test_cases = vec![
+            FreeSlotsTestCase {
+                name: "empty week",
+                events: vec![],
+                query: FreeSlotQuery::default(),
+                exp: (9..14).map(|d| (dt(9, d, 9, 0), dt(9, d, 17, 0))).collect(),
+            },
+            FreeSlotsTestCase {
+                name: "meetings split the day",
+                events: vec![
+                    busy(dt(9, 10, 8, 0), dt(9, 10, 10, 0)),
+                    busy(dt(9, 10, 12, 0), dt(9, 10, 13, 0)),
+                    busy(dt(9, 10, 13, 0), dt(9, 10, 14, 0)),
+                ],
+                query: tuesday.clone(),
+                exp: vec![
+                    (dt(9, 10, 10, 0), dt(9, 10, 12, 0)),
+                    (dt(9, 10, 14, 0), dt(9, 10, 17, 0)),
+                ],
+            },
+            FreeSlotsTestCase {
+                name: "min duration",
+                events: vec![
+                    busy(dt(9, 10, 9, 20), dt(9, 10, 12, 0)),
+                    busy(dt(9, 10, 12, 30), dt(9, 10, 17, 0)),
+                ],
+                query: tuesday.clone(),
+                exp: vec![(dt(9, 10, 12, 0), dt(9, 10, 12, 30))],
+            },
+            FreeSlotsTestCase {
+                name: "buffer",
+                events: vec![busy(dt(9, 10, 10, 0), dt(9, 10, 11, 0))],
+                query: FreeSlotQuery {
+                    buffer: TimeDelta::minutes(15),
+                    ..tuesday.clone()
+                },
+                exp: vec![
+                    (dt(9, 10, 9, 0), dt(9, 10, 9, 45)),
+                    (dt(9, 10, 11, 15), dt(9, 10, 17, 0)),
+                ],
+            },
+            FreeSlotsTestCase {
+                name: "free and working elsewhere are available",
+                events: vec![
+                    Slot {
+                        availability: Availability::WorkingElsewhere,
+                        ..Slot::new(dt(9, 10, 9, 0), dt(9, 10, 17, 0))
+                    },
+                    Slot {
+                        availability: Availability::Tentative,
+                        ..Slot::new(dt(9, 10, 13, 0), dt(9, 10, 14, 0))
+                    },
+                ],
+                query: tuesday.clone(),
+                exp: vec![
+                    (dt(9, 10, 9, 0), dt(9, 10, 13, 0)),
+                    (dt(9, 10, 14, 0), dt(9, 10, 17, 0)),
+                ],
+            },
+            FreeSlotsTestCase {
+                name: "multi day",
+                events: vec![Slot {
+                    availability: Availability::OutOfOffice,
+                    ..Slot::new(dt(9, 9, 12, 0), dt(9, 11, 12, 0))
+                }],
+                query: weekdays_except(&[WeekDay::Monday, WeekDay::Tuesday, WeekDay::Wednesday]),
+                exp: vec![
+                    (dt(9, 9, 9, 0), dt(9, 9, 12, 0)),
+                    (dt(9, 11, 12, 0), dt(9, 11, 17, 0)),
+                ],
+            },
+            FreeSlotsTestCase {
+                name: "until midnight",
+                events: vec![busy(dt(9, 14, 20, 0), dt(9, 15, 0, 0))],
+                query: FreeSlotQuery {
+                    working_hours: (t(18, 0), t(0, 0)),
+                    ..weekdays_except(&[WeekDay::Saturday, WeekDay::Sunday])
+                },
+                exp: vec![
+                    (dt(9, 14, 18, 0), dt(9, 14, 20, 0)),
+                    (dt(9, 15, 18, 0), dt(9, 16, 0, 0)),
+                ],
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut cs = CalendarSnapshot::new(wr, Tz::UTC);
+            for event in test_case.events {
+                cs.add_event(event);
+            }
+
+            let act: Vec<(DateTime<Tz>, DateTime<Tz>)> = cs
+                .free_slots(&test_case.query)
+                .iter()
+                .map(|s| (s.from, s.to))
+                .collect();
+            assert_eq!(test_case.exp, act, "{}", test_case.name);
+        }
+    }
+
+    struct DayAddSlotTestCase {
+        name: &'static str,
+        add: Vec<Slot>,
+        exp: Vec<(DateTime<Tz>, DateTime<Tz>, Availability)>,
+    }
+
+    #[test]
+    fn test_day_add_slot() {
+        let day = Day::new(NaiveDate::from_ymd_opt(2024, 9, 10).unwrap(), Tz::UTC);
+        let t = |h: u32, min: u32| dt(9, 10, h, min);
+        let free = Slot::new;
+        let with = |availability, from, to| Slot {
+            availability,
+            ..Slot::new(from, to)
+        };
+        let (start, end) = (day.start(), day.end());
+
+        let test_cases = vec![
+            DayAddSlotTestCase {
+                name: "contained",
+                add: vec![busy(t(10, 0), t(11, 0))],
+                exp: vec![
+                    (start, t(10, 0), Availability::Free),
+                    (t(10, 0), t(11, 0), Availability::Busy),
+                    (t(11, 0), end, Availability::Free),
+                ],
+            },
+            DayAddSlotTestCase {
+                name: "spans two busy slots",
+                add: vec![
+                    busy(t(10, 0), t(11, 0)),
+                    busy(t(12, 0), t(13, 0)),
+                    busy(t(10, 30), t(12, 30)),
+                ],
+                exp: vec![
+                    (start, t(10, 0), Availability::Free),
+                    (t(10, 0), t(13, 0), Availability::Busy),
+                    (t(13, 0), end, Availability::Free),
+                ],
+            },
+            DayAddSlotTestCase {
+                name: "partial overlap",
+                add: vec![busy(t(10, 0), t(11, 0)), busy(t(9, 0), t(10, 30))],
+                exp: vec![
+                    (start, t(9, 0), Availability::Free),
+                    (t(9, 0), t(11, 0), Availability::Busy),
+                    (t(11, 0), end, Availability::Free),
+                ],
+            },
+            DayAddSlotTestCase {
+                name: "duplicate",
+                add: vec![busy(t(10, 0), t(11, 0)), busy(t(10, 0), t(11, 0))],
+                exp: vec![
+                    (start, t(10, 0), Availability::Free),
+                    (t(10, 0), t(11, 0), Availability::Busy),
+                    (t(11, 0), end, Availability::Free),
+                ],
+            },
+            DayAddSlotTestCase {
+                name: "adjacent busy slots are merged",
+                add: vec![busy(t(10, 0), t(11, 0)), busy(t(11, 0), t(12, 0))],
+                exp: vec![
+                    (start, t(10, 0), Availability::Free),
+                    (t(10, 0), t(12, 0), Availability::Busy),
+                    (t(12, 0), end, Availability::Free),
+                ],
+            },
+            DayAddSlotTestCase {
+                name: "free does not override busy",
+                add: vec![busy(t(10, 0), t(12, 0)), free(t(9, 0), t(11, 0))],
+                exp: vec![
+                    (start, t(10, 0), Availability::Free),
+                    (t(10, 0), t(12, 0), Availability::Busy),
+                    (t(12, 0), end, Availability::Free),
+                ],
+            },
+            DayAddSlotTestCase {
+                name: "precedence",
+                add: vec![
+                    with(Availability::WorkingElsewhere, t(8, 0), t(17, 0)),
+                    with(Availability::Tentative, t(9, 0), t(11, 0)),
+                    busy(t(10, 0), t(12, 0)),
+                    with(Availability::Focus, t(11, 30), t(14, 0)),
+                    with(Availability::OutOfOffice, t(13, 0), t(15, 0)),
+                ],
+                exp: vec![
+                    (start, t(8, 0), Availability::Free),
+                    (t(8, 0), t(9, 0), Availability::WorkingElsewhere),
+                    (t(9, 0), t(10, 0), Availability::Tentative),
+                    (t(10, 0), t(12, 0), Availability::Busy),
+                    (t(12, 0), t(13, 0), Availability::Focus),
+                    (t(13, 0), t(15, 0), Availability::OutOfOffice),
+                    (t(15, 0), t(17, 0), Availability::WorkingElsewhere),
+                    (t(17, 0), end, Availability::Free),
+                ],
+            },
+            DayAddSlotTestCase {
+                name: "zero length",
+                add: vec![busy(t(10, 0), t(10, 0))],
+                exp: vec![(start, end, Availability::Free)],
+            },
+            DayAddSlotTestCase {
+                name: "whole day",
+                add: vec![busy(t(0, 0), t(23, 0)), busy(t(22, 0), dt(9, 11, 0, 0))],
+                exp: vec![(start, end, Availability::Busy)],
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut day = Day::new(NaiveDate::from_ymd_opt(2024, 9, 10).unwrap(), Tz::UTC);
+            for slot in test_case.add {
+                day.add_slot(slot);
+            }
+
+            let act: Vec<(DateTime<Tz>, DateTime<Tz>, Availability)> = day
+                .slots
+                .iter()
+                .map(|s| (s.from, s.to, s.availability))
+                .collect();
+            assert_eq!(test_case.exp, act, "{}", test_case.name);
+        }
+    }
+
+    mod day_properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        const DAY_MINUTES: i64 = 24 * 60;
+
+        fn slot_strategy() -> impl Strategy<Value = (i64, i64, bool)> {
+            (0..DAY_MINUTES, 0..=DAY_MINUTES, any::<bool>())
+        }
+
+        proptest! {
+            #[test]
+            fn slots_tile_the_day(inserts in prop::collection::vec(slot_strategy(), 0..20)) {
+                let mut day = Day::new(NaiveDate::from_ymd_opt(2024, 9, 10).unwrap(), Tz::UTC);
+                let mut busy_minutes: Vec<(DateTime<Tz>, DateTime<Tz>)> = Vec::new();
+
+                for (from, to, is_busy) in inserts {
+                    let slot = Slot {
+                        availability: if is_busy {
+                            Availability::Busy
+                        } else {
+                            Availability::Free
+                        },
+                        ..Slot::new(
+                            day.start() + TimeDelta::minutes(from),
+                            day.start() + TimeDelta::minutes(to),
+                        )
+                    };
+                    if is_busy && slot.from < slot.to {
+                        busy_minutes.push((slot.from, slot.to.min(day.end())));
+                    }
+                    day.add_slot(slot);
+                }
+
+                prop_assert_eq!(day.start(), day.slots.first().unwrap().from);
+                prop_assert_eq!(day.end(), day.slots.last().unwrap().to);
+                for slot in &day.slots {
+                    prop_assert!(slot.from < slot.to);
+                }
+                for pair in day.slots.windows(2) {
+                    prop_assert_eq!(pair[0].to, pair[1].from);
+                    prop_assert_ne!(pair[0].availability, pair[1].availability);
+                }
+                for (from, to) in busy_minutes {
+                    for slot in &day.slots {
+                        if slot.from < to && slot.to > from {
+                            prop_assert_eq!(Availability::Busy, slot.availability);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_day_dst() {
+        let berlin = chrono_tz::Europe::Berlin;
+
+        let spring = Day::new(date(2024, 3, 31), berlin);
+        assert_eq!(TimeDelta::hours(23), spring.length());
+        assert_eq!(
+            berlin.with_ymd_and_hms(2024, 3, 31, 23, 59, 59).unwrap(),
+            spring.end()
+        );
+
+        let autumn = Day::new(date(2024, 10, 27), berlin);
+        assert_eq!(TimeDelta::hours(25), autumn.length());
+        assert_eq!(
+            vec![(autumn.start(), autumn.end(), Availability::Free)],
+            autumn
+                .slots
+                .iter()
+                .map(|s| (s.from, s.to, s.availability))
+                .collect::<Vec<_>>()
+        );
+
+        // Chile moves the clocks forward at midnight, the day starts at 01:00.
+        let santiago = chrono_tz::America::Santiago;
+        let skipped_midnight = Day::new(date(2024, 9, 8), santiago);
+        assert_eq!(
+            santiago.with_ymd_and_hms(2024, 9, 8, 1, 0, 0).unwrap(),
+            skipped_midnight.start()
+        );
+        assert_eq!(TimeDelta::hours(23), skipped_midnight.length());
+    }
+
+    #[test]
+    fn test_add_event_dst() {
+        // Week 13 of 2024 ends with the switch to summer time on Sunday.
+        let berlin = chrono_tz::Europe::Berlin;
+        let wr = WeekRequest::new(13, 2024).unwrap();
+        let at = |d: u32, h: u32| berlin.with_ymd_and_hms(2024, 3, d, h, 0, 0).unwrap();
+
+        let mut cs = CalendarSnapshot::new(wr, berlin);
+        cs.add_event(busy(
+            at(31, 0),
+            berlin.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap(),
+        ));
+        cs.add_event(busy(at(31, 1), at(31, 4)));
+
+        let multi_day: Vec<(DateTime<Tz>, DateTime<Tz>)> =
+            cs.multi_day_slots.iter().map(|s| (s.from, s.to)).collect();
+        assert_eq!(
+            vec![(
+                at(31, 0),
+                berlin.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap()
+            )],
+            multi_day
+        );
+        assert_eq!(
+            vec![(WeekDay::Sunday, at(31, 1), at(31, 4))],
+            busy_ranges(&cs)
+        );
+        assert_eq!(TimeDelta::hours(2), at(31, 4) - at(31, 1));
+    }
+
+    // Hands out a fixed list of events and remembers what was asked for.
+    struct FakeProvider {
+        events: Vec<Event>,
+        requested: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    }
+
+    impl CalendarProvider for FakeProvider {
+        fn fetch_events(
+            &mut self,
+            from: DateTime<Utc>,
+            to: DateTime<Utc>,
+        ) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+            self.requested.push((from, to));
+            Ok(self.events.clone())
+        }
+    }
+
+    #[test]
+    fn test_from_provider() {
+        let wr = WeekRequest::new(37, 2024).unwrap();
+        let mut provider = FakeProvider {
+            events: vec![
+                Event {
+                    summary: Some("Weekly Planning".to_string()),
+                    start: EventTime::DateTime(dt(9, 9, 8, 0).with_timezone(&Utc)),
+                    end: EventTime::DateTime(dt(9, 9, 9, 0).with_timezone(&Utc)),
+                    availability: Availability::Busy,
+                    private: false,
+                },
+                Event {
+                    summary: Some("Company Offsite".to_string()),
+                    start: EventTime::Date(date(2024, 9, 11)),
+                    end: EventTime::Date(date(2024, 9, 13)),
+                    availability: Availability::Busy,
+                    private: false,
+                },
+            ],
+            requested: Vec::new(),
+        };
+
+        let cs = CalendarSnapshot::from_provider(wr, Tz::UTC, &mut provider).unwrap();
+
+        assert_eq!(
+            vec![(
+                dt(9, 9, 0, 0).with_timezone(&Utc),
+                dt(9, 16, 0, 0).with_timezone(&Utc)
+            )],
+            provider.requested
+        );
+        assert_eq!(
+            vec![(WeekDay::Monday, dt(9, 9, 8, 0), dt(9, 9, 9, 0))],
+            busy_ranges(&cs)
+        );
+        let multi_day: Vec<(DateTime<Tz>, DateTime<Tz>)> =
+            cs.multi_day_slots.iter().map(|s| (s.from, s.to)).collect();
+        assert_eq!(vec![(dt(9, 11, 0, 0), dt(9, 13, 0, 0))], multi_day);
+        assert_eq!(
+            Some("Company Offsite".to_string()),
+            cs.multi_day_slots[0].summary
+        );
+    }
+
+    #[test]
+    fn test_from_provider_time_zone() {
+        let berlin = chrono_tz::Europe::Berlin;
+        let wr = WeekRequest::new(37, 2024).unwrap();
+        let mut provider = FakeProvider {
+            events: vec![Event {
+                summary: Some("Early call".to_string()),
+                start: EventTime::DateTime(Utc.with_ymd_and_hms(2024, 9, 8, 22, 30, 0).unwrap()),
+                end: EventTime::DateTime(Utc.with_ymd_and_hms(2024, 9, 8, 23, 0, 0).unwrap()),
+                availability: Availability::Busy,
+                private: false,
+            }],
+            requested: Vec::new(),
+        };
+
+        let cs = CalendarSnapshot::from_provider(wr, berlin, &mut provider).unwrap();
+
+        assert_eq!(
+            vec![(
+                Utc.with_ymd_and_hms(2024, 9, 8, 22, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 9, 15, 22, 0, 0).unwrap()
+            )],
+            provider.requested
+        );
+        // Sunday evening in UTC is Monday morning in Berlin.
+        let at = |h: u32, min: u32| berlin.with_ymd_and_hms(2024, 9, 9, h, min, 0).unwrap();
+        assert_eq!(
+            vec![(WeekDay::Monday, at(0, 30), at(1, 0))],
+            busy_ranges(&cs)
+        );
+    }
 }
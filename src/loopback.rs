@@ -0,0 +1,291 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use oauth2::url::Url;
+
+const SUCCESS_PAGE: &str = "<html><body><h1>Snapgenda</h1>\
+    <p>Authorization received, you can close this window.</p></body></html>";
+const FAILURE_PAGE: &str = "<html><body><h1>Snapgenda</h1>\
+    <p>Authorization failed, check the terminal for details.</p></body></html>";
+// Browsers open speculative connections that never send a request. They are given up on after
+// this long, so the redirect queued behind them gets through.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Io(String),
+    StateMismatch,
+    Denied(String),
+    MissingCode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Loopback listener error: {}", e),
+            Error::StateMismatch => {
+                write!(
+                    f,
+                    "State of the authorization response does not match the request"
+                )
+            }
+            Error::Denied(e) => write!(f, "Authorization denied: {}", e),
+            Error::MissingCode(e) => write!(f, "No authorization code in {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Receives the OAuth redirect of the browser on an ephemeral port of 127.0.0.1.
+pub struct LoopbackListener {
+    listener: TcpListener,
+    read_timeout: Duration,
+}
+
+impl LoopbackListener {
+    pub fn bind() -> Result<LoopbackListener, Error> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+
+        Ok(LoopbackListener {
+            listener,
+            read_timeout: READ_TIMEOUT,
+        })
+    }
+
+    pub fn redirect_url(&self) -> Result<String, Error> {
+        let addr = self.listener.local_addr()?;
+
+        Ok(format!("http://{}", addr))
+    }
+
+    /// Blocks until the browser is redirected to the listener and returns the authorization
+    /// code. Requests without an authorization response, e.g. for a favicon, are ignored, as
+    /// are connections that fail or stay silent.
+    pub fn receive_code(&self, expected_state: &str) -> Result<String, Error> {
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            if stream.set_read_timeout(Some(self.read_timeout)).is_err() {
+                continue;
+            }
+
+            let url = match read_request_url(&stream) {
+                Ok(Some(url)) if is_authorization_response(&url) => url,
+                Ok(_) => {
+                    let _ = respond(&stream, "404 Not Found", "");
+                    continue;
+                }
+                Err(_) => continue,
+            };
+
+            let result = code_from_url(&url, expected_state);
+            let sent = match result {
+                Ok(_) => respond(&stream, "200 OK", SUCCESS_PAGE),
+                Err(_) => respond(&stream, "400 Bad Request", FAILURE_PAGE),
+            };
+            // The code is valid whether or not the browser got to show the page.
+            if let Err(e) = sent {
+                eprintln!("Could not answer the browser: {}", e);
+            }
+
+            return result;
+        }
+    }
+}
+
+/// Extracts the authorization code from a redirect URL the user copied out of the browser, for
+/// environments where the browser cannot reach the listener.
+pub fn code_from_pasted_url(pasted: &str, expected_state: &str) -> Result<String, Error> {
+    let url = Url::parse(pasted.trim()).map_err(|_| Error::MissingCode(pasted.to_string()))?;
+
+    code_from_url(&url, expected_state)
+}
+
+fn read_request_url(stream: &TcpStream) -> Result<Option<Url>, Error> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Consume the headers, closing the connection with unread data makes the browser see a
+    // reset instead of the response.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim() != "" {
+        header.clear();
+    }
+
+    // GET /?state=...&code=... HTTP/1.1
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+
+    let base = Url::parse("http://127.0.0.1").unwrap();
+    Ok(base.join(target).ok())
+}
+
+fn is_authorization_response(url: &Url) -> bool {
+    url.query_pairs()
+        .any(|(k, _)| k == "code" || k == "error" || k == "state")
+}
+
+fn code_from_url(url: &Url, expected_state: &str) -> Result<String, Error> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+
+    if param("state").as_deref() != Some(expected_state) {
+        return Err(Error::StateMismatch);
+    }
+
+    if let Some(error) = param("error") {
+        return Err(Error::Denied(error));
+    }
+
+    param("code").ok_or(Error::MissingCode(url.to_string()))
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: &str) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, thread};
+
+    use super::*;
+
+    // Plays the browser that follows the redirect and returns the HTTP status line.
+    fn redirect(redirect_url: &str, path: &str) -> String {
+        let addr = redirect_url.trim_start_matches("http://");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        match path {
+            // A speculative connection that never sends anything and stays open.
+            "silent" => {
+                thread::sleep(Duration::from_millis(300));
+                return String::new();
+            }
+            // A connection closed before the request was complete.
+            "reset" => {
+                stream.write_all(b"GET /favi").unwrap();
+                return String::new();
+            }
+            _ => {}
+        }
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr);
+        stream.write_all(request.as_bytes()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response.lines().next().unwrap().to_string()
+    }
+
+    struct ReceiveCodeTestCase {
+        name: &'static str,
+        requests: Vec<&'static str>,
+        exp: Result<String, Error>,
+        exp_status: &'static str,
+    }
+
+    #[test]
+    fn test_receive_code() {
+        let test_cases = vec![
+            ReceiveCodeTestCase {
+                name: "code",
+                requests: vec!["/?state=s3cr3t&code=4%2Fcode&scope=calendar"],
+                exp: Ok("4/code".to_string()),
+                exp_status: "HTTP/1.1 200 OK",
+            },
+            ReceiveCodeTestCase {
+                name: "favicon is ignored",
+                requests: vec!["/favicon.ico", "/?state=s3cr3t&code=abc"],
+                exp: Ok("abc".to_string()),
+                exp_status: "HTTP/1.1 200 OK",
+            },
+            ReceiveCodeTestCase {
+                name: "silent connection is given up on",
+                requests: vec!["silent", "/?state=s3cr3t&code=abc"],
+                exp: Ok("abc".to_string()),
+                exp_status: "HTTP/1.1 200 OK",
+            },
+            ReceiveCodeTestCase {
+                name: "reset connection is ignored",
+                requests: vec!["reset", "/?state=s3cr3t&code=abc"],
+                exp: Ok("abc".to_string()),
+                exp_status: "HTTP/1.1 200 OK",
+            },
+            ReceiveCodeTestCase {
+                name: "state mismatch",
+                requests: vec!["/?state=forged&code=abc"],
+                exp: Err(Error::StateMismatch),
+                exp_status: "HTTP/1.1 400 Bad Request",
+            },
+            ReceiveCodeTestCase {
+                name: "denied",
+                requests: vec!["/?state=s3cr3t&error=access_denied"],
+                exp: Err(Error::Denied("access_denied".to_string())),
+                exp_status: "HTTP/1.1 400 Bad Request",
+            },
+        ];
+
+        for test_case in test_cases {
+            let listener = LoopbackListener {
+                read_timeout: Duration::from_millis(100),
+                ..LoopbackListener::bind().unwrap()
+            };
+            let redirect_url = listener.redirect_url().unwrap();
+
+            let browser = thread::spawn(move || {
+                let mut status = String::new();
+                for path in test_case.requests {
+                    status = redirect(&redirect_url, path);
+                }
+                status
+            });
+
+            let act = listener.receive_code("s3cr3t");
+            assert_eq!(test_case.exp, act, "{}", test_case.name);
+            assert_eq!(
+                test_case.exp_status,
+                browser.join().unwrap(),
+                "{}",
+                test_case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_code_from_pasted_url() {
+        let code = code_from_pasted_url(
+            "  http://127.0.0.1:41234/?state=s3cr3t&code=4/0Adeu5B&scope=https://www.googleapis.com/auth/calendar.readonly\n",
+            "s3cr3t",
+        );
+        assert_eq!(Ok("4/0Adeu5B".to_string()), code);
+
+        let code = code_from_pasted_url("http://127.0.0.1:41234/?state=other&code=abc", "s3cr3t");
+        assert_eq!(Err(Error::StateMismatch), code);
+
+        let code = code_from_pasted_url("4/0Adeu5B", "s3cr3t");
+        assert!(matches!(code, Err(Error::MissingCode(_))));
+    }
+}
@@ -1,57 +1,240 @@
+use chrono::{Local, Utc};
 use clap::Parser;
-use std::error::Error;
+use std::{error::Error, io::IsTerminal, process::ExitCode, rc::Rc};
 
-use chrono::NaiveDate;
-use snapgenda::{AddSlot, CalendarSnapshot, WeekRequest};
+use snapgenda::{CalendarProvider, CalendarSnapshot};
+use token_cache::TokenCache;
 
 mod args;
+mod config;
 mod google;
+mod http;
+mod ics;
+mod loopback;
+mod recurrence;
 mod render;
+mod service_account;
+mod theme;
+mod token_cache;
+mod tzid;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli_args = args::Args::parse();
+const GOOGLE_TOKEN_CACHE: &str = "google";
 
-    let wr = WeekRequest::new(1, 2024)?;
-    let clndr = CalendarSnapshot::new(wr);
-    render_example(clndr);
+/// A calendar and the name it is shown with.
+type NamedProvider = (String, Box<dyn CalendarProvider>);
 
-    match cli_args.provider {
-        args::Provider::GoogleCalendar => process_google_provider(cli_args)?,
-    };
+// Exit codes of failures, following sysexits.h so scripts can tell which ones are worth a
+// retry. Usage errors exit with 2 from clap.
+const EXIT_FAILURE: u8 = 1;
+const EXIT_NOT_FOUND: u8 = 66;
+const EXIT_UNAVAILABLE: u8 = 69;
+const EXIT_TEMPORARY: u8 = 75;
+const EXIT_PROTOCOL: u8 = 76;
+const EXIT_NO_PERMISSION: u8 = 77;
 
-    Ok(())
+fn main() -> ExitCode {
+    match run(args::Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            let google_error = e.downcast_ref::<google::Error>();
+            if let Some(hint) = google_error.and_then(google::Error::hint) {
+                eprintln!("Hint: {}", hint);
+            }
+            ExitCode::from(google_error.map_or(EXIT_FAILURE, exit_code))
+        }
+    }
 }
 
-fn process_google_provider(cli_args: args::Args) -> Result<(), Box<dyn Error>> {
-    let google_args = args::GoogleArgs::new(cli_args)?;
+fn exit_code(error: &google::Error) -> u8 {
+    match error {
+        google::Error::Network(_) => EXIT_UNAVAILABLE,
+        google::Error::Http { status, .. } if *status >= 500 => EXIT_UNAVAILABLE,
+        google::Error::Http { .. } | google::Error::Parse(_) => EXIT_PROTOCOL,
+        google::Error::RateLimited { .. } => EXIT_TEMPORARY,
+        google::Error::NotFound(_) => EXIT_NOT_FOUND,
+        google::Error::Unauthorized(_)
+        | google::Error::TokenExpired(_)
+        | google::Error::PermissionDenied(_) => EXIT_NO_PERMISSION,
+        google::Error::Input(_) => EXIT_FAILURE,
+    }
+}
+
+fn run(mut cli_args: args::Args) -> Result<(), Box<dyn Error>> {
+    let free_query = match cli_args.command.take() {
+        Some(args::Command::Auth { action }) => return process_auth_command(action),
+        Some(args::Command::Free(free_args)) => Some(free_args.query()),
+        None => None,
+    };
 
-    let calendar_args = google::CalendarArgs {
-        from: NaiveDate::from_ymd_opt(2024, 9, 8).unwrap(),
-        to: NaiveDate::from_ymd_opt(2024, 9, 14).unwrap(),
-        calendar_id: google_args.calendar_email,
-        client_args: google::ClientArgs {
-            id: google_args.google_id,
-            secret: google_args.google_secret,
-        },
+    let tz = cli_args.time_zone()?;
+    let wr = cli_args.week_request(Utc::now().with_timezone(&tz).date_naive())?;
+    let config = config::Config::load(cli_args.config.as_deref())?;
+    let color = theme::color_enabled(
+        std::io::stdout().is_terminal(),
+        std::env::var_os("NO_COLOR"),
+    );
+    let options = render::MatrixOptions {
+        hours: cli_args.hours,
+        row_minutes: cli_args.row_minutes(),
+        theme: config.theme(cli_args.theme, color),
+        privacy: cli_args.privacy,
     };
 
-    let google_calendar = google::fetch_calendar(calendar_args)?;
+    let view = cli_args.view;
+
+    // Every Google calendar and every ICS file is a calendar of its own.
+    let providers: Vec<NamedProvider> = match cli_args.provider()? {
+        args::Provider::GoogleCalendar => new_google_providers(cli_args)?,
+        args::Provider::Ics => {
+            let ics_args = args::IcsArgs::new(cli_args)?;
+            ics_args
+                .files
+                .into_iter()
+                .map(|file| {
+                    let name = file
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    let provider: Box<dyn CalendarProvider> =
+                        Box::new(ics::IcsProvider::new(vec![file], tz));
+                    (name, provider)
+                })
+                .collect()
+        }
+    };
+
+    let mut calendars: Vec<(String, CalendarSnapshot)> = Vec::new();
+    for (name, mut provider) in providers {
+        calendars.push((
+            name,
+            CalendarSnapshot::from_provider(wr, tz, provider.as_mut())?,
+        ));
+    }
+
+    let mut merged = CalendarSnapshot::new(wr, tz);
+    for (_, clndr) in &calendars {
+        merged.merge(clndr)?;
+    }
+
+    if let Some(query) = free_query {
+        print!("{}", render::render_free_slots(&merged.free_slots(&query)));
+        return Ok(());
+    }
+
+    let render_matrix = match view {
+        args::View::Merged => render::render_calendar(&merged, &options),
+        args::View::SideBySide => render::render_side_by_side(&calendars, &options),
+    };
+    print!("{}", render_matrix.render());
 
     Ok(())
 }
 
-fn render_example(mut clndr: CalendarSnapshot) {
-    let day_slot = &clndr.week.days[2].slots[0];
-    let add_slot = AddSlot {
-        week_day: snapgenda::WeekDay::Wednesday,
-        slot: snapgenda::Slot {
-            from: day_slot.from.date().and_hms_opt(10, 0, 0).unwrap(),
-            to: day_slot.to.date().and_hms_opt(15, 0, 0).unwrap(),
-            availability: snapgenda::Availability::Busy,
-        },
+/// Returns a provider per calendar, named by the local part of its email.
+fn new_google_providers(cli_args: args::Args) -> Result<Vec<NamedProvider>, Box<dyn Error>> {
+    let google_args = args::GoogleArgs::new(cli_args)?;
+
+    let free_busy = Rc::new(google::FreeBusy::new(google_args.calendar_emails.clone()));
+    let endpoints = google::Endpoints::from_env();
+    let http = http::HttpClient::new(google_args.retry_policy.clone())?;
+    let (credentials, token_cache_name) = match google_args.auth {
+        args::GoogleAuth::Client {
+            id,
+            secret,
+            manual_auth,
+        } => (
+            google::Credentials::User {
+                id,
+                secret,
+                manual_auth,
+            },
+            GOOGLE_TOKEN_CACHE.to_string(),
+        ),
+        args::GoogleAuth::ServiceAccount { key, impersonate } => {
+            let key = service_account::ServiceAccountKey::load(&key)?;
+            // Tokens act as one account, switching the key or user must not reuse them.
+            let identity = impersonate.as_deref().unwrap_or(&key.client_email);
+            let name = format!("{}-{}-{}", GOOGLE_TOKEN_CACHE, key.client_email, identity);
+            (
+                google::Credentials::ServiceAccount {
+                    key,
+                    subject: impersonate,
+                },
+                name,
+            )
+        }
     };
-    clndr.add_slot(add_slot);
+    let mut out: Vec<NamedProvider> = Vec::new();
+    for calendar_email in google_args.calendar_emails {
+        let name = calendar_email
+            .split('@')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let calendar_args = google::CalendarArgs {
+            calendar_id: calendar_email,
+            client_args: google::ClientArgs {
+                credentials: credentials.clone(),
+                endpoints: endpoints.clone(),
+                http: http.clone(),
+            },
+            token_cache: TokenCache::for_provider(&token_cache_name)?,
+            free_busy: google_args.free_busy,
+        };
+        let provider = google::GoogleProvider::new(calendar_args, Rc::clone(&free_busy));
+        out.push((name, Box::new(provider)));
+    }
 
-    let render_matrix = render::render_calendar(&clndr);
-    print!("{}", render_matrix.render());
+    Ok(out)
+}
+
+/// Covers the cache of the signed in user as well as those of service accounts, which are
+/// kept per key and impersonated user.
+fn process_auth_command(action: args::AuthAction) -> Result<(), Box<dyn Error>> {
+    let token_caches = TokenCache::all_of_provider(GOOGLE_TOKEN_CACHE)?;
+    let mut logged_in = false;
+
+    match action {
+        args::AuthAction::Status => {
+            for token_cache in &token_caches {
+                let Some(token) = token_cache.load()? else {
+                    continue;
+                };
+                logged_in = true;
+                match token.expires_at {
+                    Some(expires_at) if token.is_expired(Utc::now()) => {
+                        println!(
+                            "Access token expired at {}.",
+                            expires_at.with_timezone(&Local)
+                        )
+                    }
+                    Some(expires_at) => {
+                        println!(
+                            "Access token valid until {}.",
+                            expires_at.with_timezone(&Local)
+                        )
+                    }
+                    None => println!("Access token without expiry."),
+                }
+                if token.refresh_token.is_some() {
+                    println!("A refresh token is available, expired access tokens are renewed automatically.");
+                }
+                println!("Token cache: {}", token_cache.path().display());
+            }
+        }
+        args::AuthAction::Logout => {
+            for token_cache in &token_caches {
+                if token_cache.clear()? {
+                    logged_in = true;
+                    println!("Removed {}.", token_cache.path().display());
+                }
+            }
+        }
+    }
+    if !logged_in {
+        println!("Not logged in.");
+    }
+
+    Ok(())
 }
@@ -0,0 +1,97 @@
+use std::error::Error;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+use crate::{start_of_day, Availability, Slot};
+
+/// A source of calendar events, e.g. Google Calendar.
+pub trait CalendarProvider {
+    /// Returns all events overlapping the range from (inclusive) to (exclusive).
+    fn fetch_events(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Event>, Box<dyn Error>>;
+}
+
+/// Provider independent event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// None when the provider only tells that the time is taken, e.g. free/busy.
+    pub summary: Option<String>,
+    pub start: EventTime,
+    /// Exclusive, for all-day events this is the day after the last day.
+    pub end: EventTime,
+    pub availability: Availability,
+    /// Marked private or confidential, only the owner may see the summary.
+    pub private: bool,
+}
+
+impl Event {
+    /// Timed events are converted to the time zone. All-day events last from midnight to midnight
+    /// in it, wherever they were created.
+    pub fn to_slot(&self, tz: Tz) -> Slot {
+        Slot {
+            from: self.start.in_time_zone(tz),
+            to: self.end.in_time_zone(tz),
+            availability: self.availability,
+            summary: self.summary.clone(),
+            private: self.private,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventTime {
+    /// All-day events only have a date.
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+impl EventTime {
+    fn in_time_zone(self, tz: Tz) -> DateTime<Tz> {
+        match self {
+            EventTime::Date(d) => start_of_day(&tz, d),
+            EventTime::DateTime(dt) => dt.with_timezone(&tz),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_event_to_slot() {
+        let berlin = |d: u32, h: u32| {
+            chrono_tz::Europe::Berlin
+                .with_ymd_and_hms(2024, 9, d, h, 0, 0)
+                .unwrap()
+        };
+
+        let timed = Event {
+            summary: Some("Weekly Planning".to_string()),
+            start: EventTime::DateTime(Utc.with_ymd_and_hms(2024, 9, 9, 8, 0, 0).unwrap()),
+            end: EventTime::DateTime(Utc.with_ymd_and_hms(2024, 9, 9, 9, 0, 0).unwrap()),
+            availability: Availability::Busy,
+            private: false,
+        };
+        let slot = timed.to_slot(chrono_tz::Europe::Berlin);
+        assert_eq!((berlin(9, 10), berlin(9, 11)), (slot.from, slot.to));
+        assert_eq!(Some("Weekly Planning".to_string()), slot.summary);
+
+        let all_day = Event {
+            summary: Some("Company Offsite".to_string()),
+            start: EventTime::Date(NaiveDate::from_ymd_opt(2024, 9, 11).unwrap()),
+            end: EventTime::Date(NaiveDate::from_ymd_opt(2024, 9, 13).unwrap()),
+            availability: Availability::Busy,
+            private: true,
+        };
+        let slot = all_day.to_slot(chrono_tz::Europe::Berlin);
+        assert_eq!((berlin(11, 0), berlin(13, 0)), (slot.from, slot.to));
+        assert!(slot.private);
+    }
+}
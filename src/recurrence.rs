@@ -0,0 +1,608 @@
+use std::fmt;
+
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, TimeDelta, Weekday};
+
+// Stops runaway rules, e.g. a daily event without end that started decades ago.
+const MAX_PERIODS: u32 = 100_000;
+
+#[derive(Debug, PartialEq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid RRULE: {}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Until {
+    Date(NaiveDate),
+    /// Floating or in the time zone of DTSTART.
+    Local(NaiveDateTime),
+    Utc(NaiveDateTime),
+}
+
+/// Subset of the RFC 5545 recurrence rule, e.g. FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;COUNT=10.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+    /// Weekdays with an optional ordinal, e.g. -1SU is the last sunday of the month.
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    /// Negative days count from the end of the month.
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    /// Positions within the dates of a period, negative ones count from its end. E.g. -1 with
+    /// BYDAY=MO,TU,WE,TH,FR is the last workday.
+    pub by_set_pos: Vec<i32>,
+    pub week_start: Weekday,
+}
+
+impl RRule {
+    pub fn parse(value: &str) -> Result<RRule, Error> {
+        let mut freq: Option<Frequency> = None;
+        let mut rule = RRule {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+            week_start: Weekday::Mon,
+        };
+
+        for part in value.trim().split(';').filter(|p| !p.is_empty()) {
+            let (key, val) = part
+                .split_once('=')
+                .ok_or(Error(format!("{} is not a key value pair", part)))?;
+            let invalid = || Error(format!("invalid {} {}", key, val));
+
+            match key.to_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match val.to_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(Error(format!("unsupported FREQ {}", val))),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = val.parse().map_err(|_| invalid())?;
+                    if rule.interval == 0 {
+                        return Err(invalid());
+                    }
+                }
+                "COUNT" => rule.count = Some(val.parse().map_err(|_| invalid())?),
+                "UNTIL" => rule.until = Some(parse_until(val).ok_or_else(invalid)?),
+                "BYDAY" => {
+                    rule.by_day = val
+                        .split(',')
+                        .map(parse_by_day)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(invalid)?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = val
+                        .split(',')
+                        .map(|d| d.parse::<i32>().ok().filter(|d| *d != 0 && d.abs() <= 31))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(invalid)?
+                }
+                "BYMONTH" => {
+                    rule.by_month = val
+                        .split(',')
+                        .map(|m| m.parse::<u32>().ok().filter(|m| (1..=12).contains(m)))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(invalid)?
+                }
+                "BYSETPOS" => {
+                    rule.by_set_pos = val
+                        .split(',')
+                        .map(|p| p.parse::<i32>().ok().filter(|p| *p != 0 && p.abs() <= 366))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(invalid)?
+                }
+                "WKST" => rule.week_start = parse_weekday(val).ok_or_else(invalid)?,
+                // Extensions of other applications do not change the dates.
+                key if key.starts_with("X-") => {}
+                // Ignoring a part such as BYHOUR would expand to more dates than the rule has.
+                key => return Err(Error(format!("unsupported {}", key))),
+            }
+        }
+
+        rule.freq = freq.ok_or(Error("missing FREQ".to_string()))?;
+
+        Ok(rule)
+    }
+
+    /// Returns the start of every occurrence beginning at dtstart until the rule ends or the
+    /// occurrences start after window_end. Times are local wall clock times, so a daily event at
+    /// 09:00 stays at 09:00 across DST changes. until must be converted to the same time zone.
+    pub fn expand(
+        &self,
+        dtstart: NaiveDateTime,
+        until: Option<NaiveDateTime>,
+        window_end: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        let mut out: Vec<NaiveDateTime> = Vec::new();
+        let mut count: u32 = 0;
+
+        for period in 0..MAX_PERIODS {
+            let Some(candidates) = self.period_candidates(dtstart, period) else {
+                break;
+            };
+
+            for date in candidates {
+                let occurrence = date.and_time(dtstart.time());
+                if occurrence < dtstart {
+                    continue;
+                }
+
+                if until.is_some_and(|until| occurrence > until) {
+                    return out;
+                }
+
+                if self.count.is_some_and(|max| count >= max) {
+                    return out;
+                }
+
+                if occurrence > window_end {
+                    return out;
+                }
+
+                count += 1;
+                out.push(occurrence);
+            }
+        }
+
+        out
+    }
+
+    // Returns the sorted dates of the nth period after dtstart that match the BY* parts.
+    fn period_candidates(&self, dtstart: NaiveDateTime, period: u32) -> Option<Vec<NaiveDate>> {
+        let start = dtstart.date();
+        let step = period.checked_mul(self.interval)?;
+
+        let mut dates = match self.freq {
+            Frequency::Daily => {
+                let date = start.checked_add_signed(TimeDelta::days(step as i64))?;
+                vec![date]
+                    .into_iter()
+                    .filter(|d| self.matches_month(d) && self.matches_month_day(d))
+                    .filter(|d| {
+                        self.by_day.is_empty()
+                            || self.by_day.iter().any(|(_, wd)| *wd == d.weekday())
+                    })
+                    .collect()
+            }
+            Frequency::Weekly => {
+                let week = start.week(self.week_start).first_day();
+                let week = week.checked_add_signed(TimeDelta::weeks(step as i64))?;
+                let weekdays: Vec<Weekday> = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|(_, wd)| *wd).collect()
+                };
+
+                (0..7)
+                    .map(|i| week + TimeDelta::days(i))
+                    .filter(|d| weekdays.contains(&d.weekday()))
+                    .filter(|d| self.matches_month(d))
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let month = first_of_month(start).checked_add_months(Months::new(step))?;
+                if self.matches_month(&month) {
+                    self.month_days(month, start)
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Yearly => {
+                let year = NaiveDate::from_ymd_opt(start.year(), 1, 1)?
+                    .checked_add_months(Months::new(step.checked_mul(12)?))?;
+                self.year_days(year, start)
+            }
+        };
+
+        dates.sort();
+        dates.dedup();
+        if self.by_set_pos.is_empty() {
+            return Some(dates);
+        }
+
+        let mut selected: Vec<NaiveDate> = self
+            .by_set_pos
+            .iter()
+            .filter_map(|pos| {
+                let index = if *pos > 0 {
+                    *pos - 1
+                } else {
+                    dates.len() as i32 + pos
+                };
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|i| dates.get(i).copied())
+            })
+            .collect();
+        selected.sort();
+        selected.dedup();
+        Some(selected)
+    }
+
+    fn matches_month(&self, d: &NaiveDate) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&d.month())
+    }
+
+    fn matches_month_day(&self, d: &NaiveDate) -> bool {
+        if self.by_month_day.is_empty() {
+            return true;
+        }
+
+        let last = last_of_month(*d).day() as i32;
+        self.by_month_day.iter().any(|md| {
+            let day = if *md > 0 { *md } else { last + md + 1 };
+            day == d.day() as i32
+        })
+    }
+
+    fn month_days(&self, month: NaiveDate, start: NaiveDate) -> Vec<NaiveDate> {
+        let days: Vec<NaiveDate> = month_dates(month).collect();
+
+        if self.by_day.is_empty() && self.by_month_day.is_empty() {
+            // Same day as DTSTART, months that are too short are skipped.
+            return days
+                .into_iter()
+                .filter(|d| d.day() == start.day())
+                .collect();
+        }
+
+        days.iter()
+            .copied()
+            .filter(|d| self.matches_month_day(d))
+            .filter(|d| self.by_day.is_empty() || matches_by_day(&self.by_day, d, &days))
+            .collect()
+    }
+
+    fn year_days(&self, year: NaiveDate, start: NaiveDate) -> Vec<NaiveDate> {
+        if !self.by_month.is_empty() {
+            return self
+                .by_month
+                .iter()
+                .filter_map(|m| NaiveDate::from_ymd_opt(year.year(), *m, 1))
+                .flat_map(|month| {
+                    if self.by_day.is_empty() && self.by_month_day.is_empty() {
+                        NaiveDate::from_ymd_opt(year.year(), month.month(), start.day())
+                            .into_iter()
+                            .collect()
+                    } else {
+                        self.month_days(month, start)
+                    }
+                })
+                .collect();
+        }
+
+        if self.by_day.is_empty() && self.by_month_day.is_empty() {
+            return NaiveDate::from_ymd_opt(year.year(), start.month(), start.day())
+                .into_iter()
+                .collect();
+        }
+
+        // Without BYMONTH ordinals of BYDAY count within the whole year.
+        let days: Vec<NaiveDate> = year
+            .iter_days()
+            .take_while(|d| d.year() == year.year())
+            .collect();
+        days.iter()
+            .copied()
+            .filter(|d| self.matches_month_day(d))
+            .filter(|d| self.by_day.is_empty() || matches_by_day(&self.by_day, d, &days))
+            .collect()
+    }
+}
+
+// Whether the date is one of the weekdays, honouring ordinals within the given days.
+fn matches_by_day(by_day: &[(Option<i32>, Weekday)], d: &NaiveDate, days: &[NaiveDate]) -> bool {
+    by_day.iter().any(|(ordinal, weekday)| {
+        if d.weekday() != *weekday {
+            return false;
+        }
+
+        let Some(ordinal) = ordinal else {
+            return true;
+        };
+
+        let same_weekday: Vec<&NaiveDate> =
+            days.iter().filter(|x| x.weekday() == *weekday).collect();
+        let index = if *ordinal > 0 {
+            *ordinal - 1
+        } else {
+            same_weekday.len() as i32 + ordinal
+        };
+
+        index >= 0 && same_weekday.get(index as usize) == Some(&d)
+    })
+}
+
+fn first_of_month(d: NaiveDate) -> NaiveDate {
+    d.with_day(1).unwrap()
+}
+
+fn last_of_month(d: NaiveDate) -> NaiveDate {
+    first_of_month(d)
+        .checked_add_months(Months::new(1))
+        .unwrap()
+        .pred_opt()
+        .unwrap()
+}
+
+fn month_dates(month: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+    let m = month.month();
+    month.iter_days().take_while(move |d| d.month() == m)
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    match value.to_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+// MO, 2TU, -1SU
+fn parse_by_day(value: &str) -> Option<(Option<i32>, Weekday)> {
+    let value = value.trim();
+    if value.len() < 2 {
+        return None;
+    }
+
+    let (ordinal, weekday) = value.split_at(value.len() - 2);
+    let weekday = parse_weekday(weekday)?;
+    if ordinal.is_empty() {
+        return Some((None, weekday));
+    }
+
+    let ordinal: i32 = ordinal.trim_start_matches('+').parse().ok()?;
+    if ordinal == 0 {
+        return None;
+    }
+
+    Some((Some(ordinal), weekday))
+}
+
+fn parse_until(value: &str) -> Option<Until> {
+    let value = value.trim();
+    if value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(Until::Date);
+    }
+
+    match value.strip_suffix('Z') {
+        Some(utc) => NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(Until::Utc),
+        None => NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(Until::Local),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dt(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let rule = RRule::parse(
+            "FREQ=MONTHLY;INTERVAL=2;BYDAY=2TU,-1FR;BYMONTHDAY=1,-1;UNTIL=20241231T235959Z;WKST=SU",
+        )
+        .unwrap();
+
+        assert_eq!(
+            RRule {
+                freq: Frequency::Monthly,
+                interval: 2,
+                count: None,
+                until: Some(Until::Utc(
+                    dt(2024, 12, 31, 23, 59) + TimeDelta::seconds(59)
+                )),
+                by_day: vec![(Some(2), Weekday::Tue), (Some(-1), Weekday::Fri)],
+                by_month_day: vec![1, -1],
+                by_month: vec![],
+                by_set_pos: vec![],
+                week_start: Weekday::Sun,
+            },
+            rule
+        );
+
+        let invalid = vec![
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=DAILY;COUNT",
+            "FREQ=MONTHLY;BYDAY=MO;BYSETPOS=0",
+            "FREQ=DAILY;BYHOUR=9,17",
+            "FREQ=YEARLY;BYWEEKNO=20",
+        ];
+        for value in invalid {
+            assert!(RRule::parse(value).is_err(), "{}", value);
+        }
+
+        assert!(RRule::parse("FREQ=WEEKLY;X-NAME=value").is_ok());
+    }
+
+    struct ExpandTestCase {
+        name: &'static str,
+        rule: &'static str,
+        dtstart: NaiveDateTime,
+        until: Option<NaiveDateTime>,
+        window_end: NaiveDateTime,
+        exp: Vec<NaiveDateTime>,
+    }
+
+    #[test]
+    fn test_expand() {
+        let test_cases = vec![
+            ExpandTestCase {
+                name: "daily with count",
+                rule: "FREQ=DAILY;COUNT=3",
+                dtstart: dt(2024, 9, 9, 9, 0),
+                until: None,
+                window_end: dt(2025, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 9, 9, 9, 0),
+                    dt(2024, 9, 10, 9, 0),
+                    dt(2024, 9, 11, 9, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "weekly workdays until friday",
+                rule: "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR",
+                dtstart: dt(2024, 9, 11, 9, 30),
+                until: Some(dt(2024, 9, 16, 9, 30)),
+                window_end: dt(2025, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 9, 11, 9, 30),
+                    dt(2024, 9, 12, 9, 30),
+                    dt(2024, 9, 13, 9, 30),
+                    dt(2024, 9, 16, 9, 30),
+                ],
+            },
+            ExpandTestCase {
+                name: "biweekly stops at window end",
+                rule: "FREQ=WEEKLY;INTERVAL=2;BYDAY=TH",
+                dtstart: dt(2024, 8, 1, 14, 0),
+                until: None,
+                window_end: dt(2024, 9, 16, 0, 0),
+                exp: vec![
+                    dt(2024, 8, 1, 14, 0),
+                    dt(2024, 8, 15, 14, 0),
+                    dt(2024, 8, 29, 14, 0),
+                    dt(2024, 9, 12, 14, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "monthly on the 31st skips short months",
+                rule: "FREQ=MONTHLY;COUNT=3",
+                dtstart: dt(2024, 8, 31, 10, 0),
+                until: None,
+                window_end: dt(2025, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 8, 31, 10, 0),
+                    dt(2024, 10, 31, 10, 0),
+                    dt(2024, 12, 31, 10, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "monthly last friday",
+                rule: "FREQ=MONTHLY;BYDAY=-1FR;COUNT=3",
+                dtstart: dt(2024, 9, 1, 16, 0),
+                until: None,
+                window_end: dt(2025, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 9, 27, 16, 0),
+                    dt(2024, 10, 25, 16, 0),
+                    dt(2024, 11, 29, 16, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "monthly by month day",
+                rule: "FREQ=MONTHLY;BYMONTHDAY=1,-1;COUNT=4",
+                dtstart: dt(2024, 9, 1, 8, 0),
+                until: None,
+                window_end: dt(2025, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 9, 1, 8, 0),
+                    dt(2024, 9, 30, 8, 0),
+                    dt(2024, 10, 1, 8, 0),
+                    dt(2024, 10, 31, 8, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "monthly last workday",
+                rule: "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;COUNT=3",
+                dtstart: dt(2024, 9, 1, 10, 0),
+                until: None,
+                window_end: dt(2025, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 9, 30, 10, 0),
+                    dt(2024, 10, 31, 10, 0),
+                    dt(2024, 11, 29, 10, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "monthly first and second weekend day",
+                rule: "FREQ=MONTHLY;BYDAY=SA,SU;BYSETPOS=1,2,9;COUNT=4",
+                dtstart: dt(2024, 9, 1, 10, 0),
+                until: None,
+                window_end: dt(2025, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 9, 1, 10, 0),
+                    dt(2024, 9, 7, 10, 0),
+                    dt(2024, 9, 29, 10, 0),
+                    dt(2024, 10, 5, 10, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "yearly leap day",
+                rule: "FREQ=YEARLY",
+                dtstart: dt(2020, 2, 29, 0, 0),
+                until: None,
+                window_end: dt(2029, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2020, 2, 29, 0, 0),
+                    dt(2024, 2, 29, 0, 0),
+                    dt(2028, 2, 29, 0, 0),
+                ],
+            },
+            ExpandTestCase {
+                name: "yearly by month and day",
+                rule: "FREQ=YEARLY;BYMONTH=3,10;BYDAY=-1SU;COUNT=3",
+                dtstart: dt(2024, 1, 1, 3, 0),
+                until: None,
+                window_end: dt(2030, 1, 1, 0, 0),
+                exp: vec![
+                    dt(2024, 3, 31, 3, 0),
+                    dt(2024, 10, 27, 3, 0),
+                    dt(2025, 3, 30, 3, 0),
+                ],
+            },
+        ];
+
+        for test_case in test_cases {
+            let rule = RRule::parse(test_case.rule).unwrap();
+            let act = rule.expand(test_case.dtstart, test_case.until, test_case.window_end);
+            assert_eq!(test_case.exp, act, "{}", test_case.name);
+        }
+    }
+}
@@ -1,20 +1,211 @@
-use chrono::{NaiveTime, TimeDelta};
-use snapgenda::{CalendarSnapshot, Day, WeekDay};
+use std::str::FromStr;
 
-const WEEK_DAYS: [WeekDay; 7] = WeekDay::week_days();
+use chrono::{Datelike, NaiveTime, TimeDelta, Timelike};
+use clap::ValueEnum;
+use snapgenda::{Availability, CalendarSnapshot, Day, Slot, WeekDay};
+
+use crate::theme::{visible_width, StateStyle, Theme};
+
+const CELL_WIDTH: usize = 9;
+
+/// Hours of the day shown in the matrix, from (inclusive) until to (exclusive), e.g. 08-19.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HourRange {
+    from: u32,
+    to: u32,
+}
+
+impl HourRange {
+    pub fn new(from: u32, to: u32) -> Result<HourRange, String> {
+        if to > 24 {
+            return Err(format!("hour {} is after the end of the day", to));
+        }
+
+        if from >= to {
+            return Err(format!("hour {} must be before hour {}", from, to));
+        }
+
+        Ok(HourRange { from, to })
+    }
+
+    /// Returns the range as wall clock times, hour 24 is 00:00 at the end of the day.
+    pub fn times(&self) -> (NaiveTime, NaiveTime) {
+        // Unwrap is safe here, new checks the hours.
+        let time = |h: u32| NaiveTime::from_hms_opt(h % 24, 0, 0).unwrap();
+        (time(self.from), time(self.to))
+    }
+}
+
+impl Default for HourRange {
+    fn default() -> Self {
+        HourRange { from: 0, to: 24 }
+    }
+}
+
+impl FromStr for HourRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} is not an hour range like 08-19", s);
+
+        let (from, to) = s.split_once('-').ok_or_else(invalid)?;
+        let from: u32 = from.trim().parse().map_err(|_| invalid())?;
+        let to: u32 = to.trim().parse().map_err(|_| invalid())?;
+
+        HourRange::new(from, to)
+    }
+}
+
+/// How much the matrix tells about events.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum Privacy {
+    /// Only whether the time is busy.
+    #[default]
+    BusyOnly,
+    /// Event titles, cut to fit the cells.
+    Titles,
+    /// A category such as Meeting instead of the title.
+    Redacted,
+}
+
+impl Privacy {
+    /// Returns what to show for the slot, None when only its availability is shown. Private
+    /// events never show more than their category, slots without a summary such as free/busy
+    /// intervals not even that.
+    fn label(&self, slot: &Slot) -> Option<String> {
+        let summary = slot.summary.as_ref()?;
+        match self {
+            Privacy::BusyOnly => None,
+            Privacy::Titles if !slot.private => Some(summary.clone()),
+            Privacy::Titles | Privacy::Redacted => Some(category(slot.availability).to_string()),
+        }
+    }
+}
+
+fn category(availability: Availability) -> &'static str {
+    match availability {
+        Availability::Busy => "Meeting",
+        Availability::Free => "Free",
+        Availability::Tentative => "Tentative",
+        Availability::OutOfOffice => "Out of office",
+        Availability::Focus => "Focus",
+        Availability::WorkingElsewhere => "Remote",
+    }
+}
+
+pub struct MatrixOptions {
+    pub hours: HourRange,
+    /// Length of a row, one of 60, 30 or 15 minutes.
+    pub row_minutes: u32,
+    pub theme: Theme,
+    pub privacy: Privacy,
+}
+
+impl Default for MatrixOptions {
+    fn default() -> Self {
+        MatrixOptions {
+            hours: HourRange::default(),
+            row_minutes: 60,
+            theme: Theme::default(),
+            privacy: Privacy::default(),
+        }
+    }
+}
+
+impl MatrixOptions {
+    fn time_slots(&self) -> Vec<TimeSlot> {
+        let mut out: Vec<TimeSlot> = Vec::new();
+
+        let mut minute = self.hours.from * 60;
+        while minute < self.hours.to * 60 {
+            // Unwrap is safe here, the range ends at 24:00 at most.
+            let from = NaiveTime::from_hms_opt(minute / 60, minute % 60, 0).unwrap();
+            out.push(TimeSlot::new(from, self.row_minutes));
+            minute += self.row_minutes;
+        }
+
+        out
+    }
+}
 
 pub struct Matrix {
     rows: Vec<Row>,
 }
 
 impl Matrix {
-    fn new(cs: &CalendarSnapshot) -> Matrix {
+    fn new(cs: &CalendarSnapshot, options: &MatrixOptions) -> Matrix {
+        let time_slots = options.time_slots();
+
         let mut cols: Vec<Column> = Vec::new();
-        cols.push(Column::new_timeslot());
-        cols.extend(Column::from_days(&cs.week.days));
+        cols.push(Column::new_timeslot(&time_slots));
+        cols.extend(Column::from_days(&cs.week.days, &time_slots, options));
         let mut rows: Vec<Row> = Vec::new();
         rows.extend(Row::new_rows(&cols));
 
+        // Banners go between the header and the hours.
+        let banners = Row::new_banner_rows(
+            Banner::stack(Banner::from_snapshot(cs, options.privacy)),
+            cols.len() - 1,
+            &options.theme,
+        );
+        rows.splice(1..1, banners);
+
+        Matrix { rows }
+    }
+
+    /// Every day gets one column per calendar, headed by the calendar's name. Multi-day slots
+    /// are drawn per day so they stay within their calendar's columns.
+    fn new_side_by_side(
+        calendars: &[(String, CalendarSnapshot)],
+        options: &MatrixOptions,
+    ) -> Matrix {
+        let time_slots = options.time_slots();
+
+        let mut cols: Vec<Column> = Vec::new();
+        cols.push(Column::new_timeslot(&time_slots));
+        for day in 0..7 {
+            for (name, cs) in calendars {
+                let header = abbreviate(name, CELL_WIDTH - 1);
+                cols.push(Column::from_day(
+                    &cs.week.days[day],
+                    &header,
+                    &time_slots,
+                    options,
+                ));
+            }
+        }
+        let mut rows: Vec<Row> = Vec::new();
+        rows.extend(Row::new_rows(&cols));
+
+        let mut banners: Vec<Banner> = Vec::new();
+        for (i, (_, cs)) in calendars.iter().enumerate() {
+            for banner in Banner::from_snapshot(cs, options.privacy) {
+                for day in banner.first..=banner.last {
+                    let col = day * calendars.len() + i;
+                    banners.push(Banner {
+                        first: col,
+                        last: col,
+                        summary: banner.summary.clone(),
+                        availability: banner.availability,
+                    });
+                }
+            }
+        }
+        let banners = Row::new_banner_rows(Banner::stack(banners), cols.len() - 1, &options.theme);
+        rows.splice(1..1, banners);
+
+        // Day names span the columns of their calendars.
+        let mut header = vec![Cell::new_empty()];
+        if let Some((_, cs)) = calendars.first() {
+            for day in &cs.week.days {
+                header.push(Cell {
+                    values: vec![day.week_day().short_name()],
+                    span: calendars.len(),
+                });
+            }
+        }
+        rows.insert(0, Row { cells: header });
+
         Matrix { rows }
     }
 
@@ -35,14 +226,15 @@ struct Row {
 }
 
 impl Row {
-    fn new_rows(cols: &Vec<Column>) -> Vec<Row> {
+    fn new_rows(cols: &[Column]) -> Vec<Row> {
         let mut rows: Vec<Row> = Vec::new();
 
-        for i in 0..=25 {
+        let row_count = cols.iter().map(|c| c.cells.len()).max().unwrap_or(0);
+        for i in 0..row_count {
             let mut row_cells: Vec<Cell> = Vec::new();
             for col in cols {
-                let cell = &col.cells[i];
-                row_cells.push(cell.clone());
+                let cell = col.cells.get(i).cloned().unwrap_or(Cell::new_empty());
+                row_cells.push(cell);
             }
             let row = Row { cells: row_cells };
             rows.push(row);
@@ -51,6 +243,37 @@ impl Row {
         rows
     }
 
+    fn new_banner_rows(lanes: Vec<Vec<Banner>>, columns: usize, theme: &Theme) -> Vec<Row> {
+        let mut rows: Vec<Row> = Vec::new();
+
+        for (i, lane) in lanes.iter().enumerate() {
+            let label = if i == 0 { "All day" } else { "" };
+            let width = Cell::new_buffer().chars().count();
+            let mut cells = vec![Cell::new(&format!("{:^width$}", label))];
+
+            let mut col = 0;
+            while col < columns {
+                match lane.iter().find(|b| b.first == col) {
+                    Some(banner) => {
+                        cells.push(Cell {
+                            values: vec![banner.render(theme)],
+                            span: banner.span(),
+                        });
+                        col += banner.span();
+                    }
+                    None => {
+                        cells.push(Cell::new(&String::new()));
+                        col += 1;
+                    }
+                }
+            }
+
+            rows.push(Row { cells });
+        }
+
+        rows
+    }
+
     fn render(&self) -> String {
         let mut out = String::new();
 
@@ -67,41 +290,63 @@ struct Column {
 }
 
 impl Column {
-    fn new_timeslot() -> Column {
-        let time_slots = TimeSlot::new_range();
+    fn new_timeslot(time_slots: &[TimeSlot]) -> Column {
         let mut cells: Vec<Cell> = Vec::new();
 
         let render_buffer = Cell::new_buffer();
         cells.push(Cell::new(&render_buffer));
+        cells.push(Cell::new(&render_buffer));
 
         for slot in time_slots {
             let v = slot.render();
-            let cell = Cell { values: vec![v] };
+            let cell = Cell {
+                values: vec![v],
+                span: 1,
+            };
             cells.push(cell);
         }
         cells.push(Cell::new(&render_buffer));
-        cells.push(Cell::new(&render_buffer));
 
         Column { cells }
     }
 
-    fn from_day(day: &Day, week_day: &str) -> Column {
+    fn from_day(
+        day: &Day,
+        week_day: &str,
+        time_slots: &[TimeSlot],
+        options: &MatrixOptions,
+    ) -> Column {
+        let theme = &options.theme;
         let mut cells: Vec<Cell> = Vec::new();
         cells.push(Cell::new_header_cell(week_day));
+        cells.push(Cell::new(&theme.top));
 
-        for slot in &day.slots {
-            let slot_cells = Cell::new_cells(&slot);
-            cells.extend(slot_cells);
+        // Rows with the same state and label form a block, its bottom border is drawn into its
+        // last row. The column border closes the last block.
+        let rows: Vec<(RowState, Option<String>)> = time_slots
+            .iter()
+            .map(|ts| (RowState::of(day, ts), row_label(day, ts, options.privacy)))
+            .collect();
+        for (i, (state, label)) in rows.iter().enumerate() {
+            let ends_block = i + 1 < rows.len() && rows[i + 1] != (*state, label.clone());
+            cells.push(Cell::new_row_cell(
+                *state,
+                label.as_deref(),
+                ends_block,
+                theme,
+            ));
         }
 
+        cells.push(Cell::new(&theme.bottom));
+
         Column { cells }
     }
 
-    fn from_days(days: &[Day; 7]) -> Vec<Column> {
+    fn from_days(days: &[Day; 7], time_slots: &[TimeSlot], options: &MatrixOptions) -> Vec<Column> {
         let mut out: Vec<Column> = Vec::new();
 
-        for (day, week_day) in days.iter().zip(WEEK_DAYS) {
-            let colmn = Column::from_day(&day, &week_day.short_name());
+        for day in days {
+            let colmn = Column::from_day(day, &day.week_day().short_name(), time_slots, options);
             out.push(colmn);
         }
 
@@ -109,20 +354,101 @@ impl Column {
     }
 }
 
+/// How much of a row is booked.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum RowState {
+    Free,
+    Partial,
+    /// Booked for the whole row, with the availability that takes precedence within it.
+    Full(Availability),
+}
+
+impl RowState {
+    /// Rows are wall clock times, so days with a DST change still have one row per hour. The
+    /// repeated hour shares a row, the skipped one shows the slot around it.
+    fn of(day: &Day, time_slot: &TimeSlot) -> RowState {
+        let mut booked = TimeDelta::zero();
+        let mut availability = Availability::Free;
+        for (slot, overlap) in booked_overlaps(day, time_slot) {
+            booked += overlap;
+            availability = availability.max_priority(slot.availability);
+        }
+        let row_length = TimeDelta::minutes(time_slot.minutes as i64);
+
+        // Day::end is a second before midnight, the last row still counts as fully booked.
+        if booked >= row_length - TimeDelta::seconds(1) {
+            RowState::Full(availability)
+        } else if booked > TimeDelta::zero() {
+            RowState::Partial
+        } else {
+            RowState::Free
+        }
+    }
+
+    fn style<'a>(&self, theme: &'a Theme) -> &'a StateStyle {
+        match self {
+            RowState::Free => &theme.free,
+            RowState::Partial => &theme.partial,
+            RowState::Full(availability) => theme.style(*availability),
+        }
+    }
+}
+
+/// Returns the slots of the day overlapping the row that are not free, and by how much.
+fn booked_overlaps<'a>(
+    day: &'a Day,
+    time_slot: &TimeSlot,
+) -> impl Iterator<Item = (&'a Slot, TimeDelta)> {
+    let row_from = day.date().and_time(time_slot.from);
+    let row_to = row_from + TimeDelta::minutes(time_slot.minutes as i64);
+
+    day.slots
+        .iter()
+        .filter(|slot| slot.availability != Availability::Free)
+        .filter_map(move |slot| {
+            // Within the repeated hour the wall clock runs backwards.
+            let (from, to) = (slot.from.naive_local(), slot.to.naive_local());
+            let (from, to) = (from.min(to), from.max(to));
+
+            let overlap_from = from.max(row_from);
+            let overlap_to = to.min(row_to);
+            (overlap_from < overlap_to).then(|| (slot, overlap_to - overlap_from))
+        })
+}
+
+/// Labels a row with the event taking up most of it, the earlier one on a tie.
+fn row_label(day: &Day, time_slot: &TimeSlot, privacy: Privacy) -> Option<String> {
+    let mut longest: Option<(&Slot, TimeDelta)> = None;
+    for (slot, overlap) in booked_overlaps(day, time_slot) {
+        if longest.is_none_or(|(_, d)| overlap > d) {
+            longest = Some((slot, overlap));
+        }
+    }
+
+    longest.and_then(|(slot, _)| privacy.label(slot))
+}
+
 #[derive(Debug, PartialEq, Clone)]
 struct Cell {
     values: Vec<String>,
+    /// Number of day columns the cell covers.
+    span: usize,
 }
 
 impl Cell {
     fn new(value: &String) -> Cell {
         Cell {
             values: vec![value.to_string()],
+            span: 1,
         }
     }
 
+    fn new_empty() -> Cell {
+        Cell::new(&Cell::new_buffer())
+    }
+
     fn new_buffer() -> String {
-        let ts = TimeSlot::new(1);
+        let ts = TimeSlot::new(NaiveTime::MIN, 60);
         let ts_str = ts.render();
         let mut replaced = String::new();
         ts_str.chars().for_each(|_| {
@@ -134,67 +460,196 @@ impl Cell {
 
     fn render(&self) -> String {
         let mut out = String::new();
+        let width = CELL_WIDTH * self.span;
         for v in &self.values {
-            let value = format!("{:^9}", v);
-            out.push_str(&value.to_string())
+            // Escape codes take no space, so padding goes by the visible width.
+            let padding = width.saturating_sub(visible_width(v));
+            let (left, right) = (padding / 2, padding - padding / 2);
+            out.push_str(&format!("{}{}{}", " ".repeat(left), v, " ".repeat(right)));
         }
-        return out;
+        out
     }
 
     fn new_header_cell(week_day: &str) -> Cell {
-        return Cell {
+        Cell {
             values: vec![week_day.to_string()],
+            span: 1,
+        }
+    }
+
+    // A label replaces the fill, the rest of the cell is padded with the first fill character.
+    fn new_row_cell(state: RowState, label: Option<&str>, ends_block: bool, theme: &Theme) -> Cell {
+        let style = state.style(theme);
+        let mut fill = if ends_block {
+            style.block_end.clone()
+        } else {
+            style.fill.clone()
         };
+        if let Some(label) = label {
+            let width = fill.chars().count();
+            let pad = fill.chars().next().unwrap_or(' ');
+            let label = abbreviate(label, width);
+            let padding = width - label.chars().count();
+            fill = format!("{}{}", label, pad.to_string().repeat(padding));
+        }
+
+        Cell {
+            values: vec![format!(
+                "{}{}{}",
+                theme.side,
+                theme.paint(style, &fill),
+                theme.side
+            )],
+            span: 1,
+        }
     }
+}
 
-    fn new_cells(s: &snapgenda::Slot) -> Vec<Cell> {
-        let mut out: Vec<Cell> = Vec::new();
-        out.push(Cell {
-            values: vec!["|¯¯¯¯¯¯|".to_string()],
-        });
+/// A multi-day slot drawn as a bar across the columns first until last.
+#[derive(Debug, PartialEq)]
+struct Banner {
+    first: usize,
+    last: usize,
+    summary: String,
+    availability: Availability,
+}
 
-        let mut cursor = s.from;
-        while cursor < s.to {
-            let box_value = format!("| {} |", s.availability.to_string());
+impl Banner {
+    fn from_snapshot(cs: &CalendarSnapshot, privacy: Privacy) -> Vec<Banner> {
+        let day_of = |t| {
+            cs.week
+                .days
+                .iter()
+                .position(|d| d.start() <= t && t <= d.end())
+        };
 
-            let c = Cell {
-                values: vec![box_value],
-            };
-            out.push(c);
+        let mut out: Vec<Banner> = Vec::new();
+        for slot in &cs.multi_day_slots {
+            // The end is exclusive, an event until midnight does not cover the next day.
+            let last_second = slot.to - TimeDelta::seconds(1);
+            if let (Some(first), Some(last)) = (day_of(slot.from), day_of(last_second)) {
+                out.push(Banner {
+                    first,
+                    last,
+                    summary: privacy.label(slot).unwrap_or(slot.availability.to_string()),
+                    availability: slot.availability,
+                });
+            }
+        }
+
+        out
+    }
+
+    /// Puts every banner into the first lane where it does not overlap another one. Each lane
+    /// is rendered as one row.
+    fn stack(mut banners: Vec<Banner>) -> Vec<Vec<Banner>> {
+        banners.sort_by_key(|b| (b.first, std::cmp::Reverse(b.last)));
 
-            cursor = cursor + TimeDelta::hours(1);
-            if cursor >= s.to {
-                break;
+        let mut lanes: Vec<Vec<Banner>> = Vec::new();
+        for banner in banners {
+            let lane = lanes
+                .iter_mut()
+                .find(|lane| lane.last().is_some_and(|b| b.last < banner.first));
+            match lane {
+                Some(lane) => lane.push(banner),
+                None => lanes.push(vec![banner]),
             }
         }
-        out.push(Cell {
-            values: vec!["|______|".to_string()],
-        });
 
-        return out;
+        lanes
+    }
+
+    fn span(&self) -> usize {
+        self.last - self.first + 1
+    }
+
+    // [Company Offsite   ] for busy and (Reminder   ) for free events in the ASCII theme, as
+    // wide as the day columns it covers.
+    fn render(&self, theme: &Theme) -> String {
+        let style = theme.style(self.availability);
+        let width = CELL_WIDTH * self.span() - 3;
+
+        let banner = format!(
+            "{}{:<width$}{}",
+            style.banner_open,
+            truncate(&self.summary, width),
+            style.banner_close
+        );
+        theme.paint(style, &banner)
     }
 }
 
-pub fn render_calendar(cs: &CalendarSnapshot) -> Matrix {
-    let m = Matrix::new(cs);
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+
+    if max <= 3 {
+        return s.chars().take(max).collect();
+    }
 
-    m
+    let mut out: String = s.chars().take(max - 3).collect();
+    out.push_str("...");
+    out
+}
+
+// Cells are too narrow for "...", a single dot marks the cut.
+fn abbreviate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max || max < 2 {
+        return s.chars().take(max).collect();
+    }
+
+    let mut out: String = s.chars().take(max - 1).collect();
+    out.push('.');
+    out
+}
+
+pub fn render_calendar(cs: &CalendarSnapshot, options: &MatrixOptions) -> Matrix {
+    Matrix::new(cs, options)
+}
+
+/// Renders the calendars next to each other, one column per calendar and day.
+pub fn render_side_by_side(
+    calendars: &[(String, CalendarSnapshot)],
+    options: &MatrixOptions,
+) -> Matrix {
+    Matrix::new_side_by_side(calendars, options)
+}
+
+/// Lists free slots one per line, e.g. Tue 14:00–15:30. A slot until midnight ends at 24:00.
+pub fn render_free_slots(slots: &[Slot]) -> String {
+    let mut out = String::new();
+
+    for slot in slots {
+        let to = if slot.to.date_naive() > slot.from.date_naive()
+            && slot.to.num_seconds_from_midnight() == 0
+        {
+            "24:00".to_string()
+        } else {
+            slot.to.format("%H:%M").to_string()
+        };
+        out.push_str(&format!(
+            "{} {}–{}\n",
+            WeekDay::from(slot.from.weekday()).short_name(),
+            slot.from.format("%H:%M"),
+            to
+        ));
+    }
+
+    out
 }
 
 #[derive(Debug, PartialEq)]
 struct TimeSlot {
     from: NaiveTime,
     to: NaiveTime,
+    minutes: u32,
 }
 
 impl TimeSlot {
-    fn new(from: u32) -> TimeSlot {
-        let from_time = NaiveTime::from_hms_opt(from, 0, 0).unwrap();
-        let (to_time, _) = from_time.overflowing_add_signed(TimeDelta::hours(1));
-        TimeSlot {
-            from: from_time,
-            to: to_time,
-        }
+    fn new(from: NaiveTime, minutes: u32) -> TimeSlot {
+        let (to, _) = from.overflowing_add_signed(TimeDelta::minutes(minutes as i64));
+        TimeSlot { from, to, minutes }
     }
 
     fn render(&self) -> String {
@@ -205,58 +660,514 @@ impl TimeSlot {
         );
         format!("{:^9}", s)
     }
-
-    fn new_range() -> Vec<TimeSlot> {
-        let mut out: Vec<TimeSlot> = Vec::new();
-        for i in 0..=23 {
-            out.push(TimeSlot::new(i));
-        }
-        out
-    }
 }
 
 #[cfg(test)]
 mod tests {
-    use chrono::{NaiveDate, NaiveDateTime};
-    use snapgenda::{Availability, Slot};
+    use chrono::TimeZone;
+    use chrono_tz::Tz;
+    use snapgenda::WeekRequest;
 
     use super::*;
 
+    fn event(
+        from: chrono::DateTime<Tz>,
+        to: chrono::DateTime<Tz>,
+        availability: Availability,
+        summary: &str,
+    ) -> Slot {
+        Slot {
+            availability,
+            summary: Some(summary.to_string()),
+            ..Slot::new(from, to)
+        }
+    }
+
+    #[test]
+    fn test_hour_range_from_str() {
+        assert_eq!(Ok(HourRange { from: 8, to: 19 }), "08-19".parse());
+        assert_eq!(Ok(HourRange { from: 0, to: 24 }), "0-24".parse());
+        assert!("19-08".parse::<HourRange>().is_err());
+        assert!("08-25".parse::<HourRange>().is_err());
+        assert!("08:00-19:00".parse::<HourRange>().is_err());
+        assert!("8".parse::<HourRange>().is_err());
+    }
+
+    #[test]
+    fn test_hour_range_times() {
+        let t = |h: u32| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+        assert_eq!((t(9), t(17)), HourRange::new(9, 17).unwrap().times());
+        assert_eq!((t(0), t(0)), HourRange::default().times());
+    }
+
     #[test]
-    fn test_cell_new_cells() {
-        let date = NaiveDate::from_ymd_opt(2024, 8, 21).unwrap();
-        let from = NaiveDateTime::new(date, NaiveTime::from_hms_opt(10, 0, 0).unwrap());
-        let to = NaiveDateTime::new(date, NaiveTime::from_hms_opt(15, 30, 0).unwrap());
-        let s = Slot::new(from, to);
-
-        let exp_cells = vec![
-            // 10-11
-            Cell {
-                values: vec![Availability::Free.to_string()],
+    fn test_render_free_slots() {
+        let at =
+            |d: u32, h: u32, min: u32| Tz::UTC.with_ymd_and_hms(2024, 9, d, h, min, 0).unwrap();
+        let slots = vec![
+            Slot::new(at(10, 14, 0), at(10, 15, 30)),
+            Slot::new(at(13, 18, 0), at(14, 0, 0)),
+        ];
+
+        assert_eq!(
+            "Tue 14:00–15:30\nFri 18:00–24:00\n",
+            render_free_slots(&slots)
+        );
+        assert_eq!("", render_free_slots(&[]));
+    }
+
+    struct RowStateTestCase {
+        row_minutes: u32,
+        exp: Vec<(&'static str, RowState)>,
+    }
+
+    #[test]
+    fn test_row_state() {
+        // Tuesday of week 37 with a meeting from 09:30 until 10:15.
+        let mut cs = CalendarSnapshot::new(WeekRequest::new(37, 2024).unwrap(), Tz::UTC);
+        cs.add_event(event(
+            Tz::UTC.with_ymd_and_hms(2024, 9, 10, 9, 30, 0).unwrap(),
+            Tz::UTC.with_ymd_and_hms(2024, 9, 10, 10, 15, 0).unwrap(),
+            Availability::Busy,
+            "Meeting",
+        ));
+        let day = &cs.week.days[1];
+
+        let test_cases = vec![
+            RowStateTestCase {
+                row_minutes: 60,
+                exp: vec![
+                    ("08:00", RowState::Free),
+                    ("09:00", RowState::Partial),
+                    ("10:00", RowState::Partial),
+                    ("11:00", RowState::Free),
+                ],
             },
-            // 11-12
-            Cell {
-                values: vec![Availability::Free.to_string()],
+            RowStateTestCase {
+                row_minutes: 30,
+                exp: vec![
+                    ("09:00", RowState::Free),
+                    ("09:30", RowState::Full(Availability::Busy)),
+                    ("10:00", RowState::Partial),
+                    ("10:30", RowState::Free),
+                ],
             },
-            // 12-13
-            Cell {
-                values: vec![Availability::Free.to_string()],
+            RowStateTestCase {
+                row_minutes: 15,
+                exp: vec![
+                    ("09:15", RowState::Free),
+                    ("09:30", RowState::Full(Availability::Busy)),
+                    ("10:00", RowState::Full(Availability::Busy)),
+                    ("10:15", RowState::Free),
+                ],
             },
-            // 13-14
-            Cell {
-                values: vec![Availability::Free.to_string()],
+        ];
+
+        for test_case in test_cases {
+            for (from, exp) in test_case.exp {
+                let from = NaiveTime::parse_from_str(from, "%H:%M").unwrap();
+                let act = RowState::of(day, &TimeSlot::new(from, test_case.row_minutes));
+                assert_eq!(exp, act, "{} at {}", test_case.row_minutes, from);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_window() {
+        let cs = CalendarSnapshot::new(WeekRequest::new(37, 2024).unwrap(), Tz::UTC);
+        let options = MatrixOptions {
+            hours: "08-19".parse().unwrap(),
+            row_minutes: 30,
+            ..Default::default()
+        };
+
+        let out = render_calendar(&cs, &options).render();
+        let lines: Vec<&str> = out.lines().collect();
+
+        // Header, top border, 22 rows and the bottom border.
+        assert_eq!(25, lines.len());
+        assert!(lines[2].starts_with("08:00 - 08:30"), "{}", lines[2]);
+        assert!(lines[23].starts_with("18:30 - 19:00"), "{}", lines[23]);
+        let width = lines[0].chars().count();
+        assert!(lines.iter().all(|l| l.chars().count() == width));
+    }
+
+    #[test]
+    fn test_row_state_dst() {
+        let berlin = chrono_tz::Europe::Berlin;
+        let options = MatrixOptions::default();
+
+        // Week 43 ends with the 25 hour day, 02:00 until 03:00 happens twice.
+        let mut cs = CalendarSnapshot::new(WeekRequest::new(43, 2024).unwrap(), berlin);
+        let twice_two =
+            berlin.with_ymd_and_hms(2024, 10, 27, 1, 0, 0).unwrap() + TimeDelta::hours(1);
+        cs.add_event(event(
+            twice_two,
+            twice_two + TimeDelta::hours(2),
+            Availability::Busy,
+            "Meeting",
+        ));
+        let sunday = &cs.week.days[6];
+
+        let states: Vec<RowState> = options
+            .time_slots()
+            .iter()
+            .map(|ts| RowState::of(sunday, ts))
+            .collect();
+        assert_eq!(24, states.len());
+        assert_eq!(RowState::Free, states[1]);
+        assert_eq!(RowState::Full(Availability::Busy), states[2]);
+        assert_eq!(RowState::Free, states[3]);
+    }
+
+    struct RowLabelTestCase {
+        privacy: Privacy,
+        exp: Vec<Option<&'static str>>,
+    }
+
+    #[test]
+    fn test_row_label() {
+        // Tuesday with a free/busy interval at 07:00, a private appointment at 08:00, a
+        // planning from 09:00 until 10:45 and a review from 10:45.
+        let at = |h: u32, min: u32| Tz::UTC.with_ymd_and_hms(2024, 9, 10, h, min, 0).unwrap();
+        let mut cs = CalendarSnapshot::new(WeekRequest::new(37, 2024).unwrap(), Tz::UTC);
+        cs.add_event(Slot {
+            availability: Availability::Busy,
+            ..Slot::new(at(7, 0), at(7, 30))
+        });
+        cs.add_event(Slot {
+            private: true,
+            ..event(at(8, 0), at(8, 30), Availability::Busy, "Doctor")
+        });
+        cs.add_event(event(at(9, 0), at(10, 45), Availability::Busy, "Planning"));
+        cs.add_event(event(at(10, 45), at(12, 0), Availability::Busy, "Review"));
+        let day = &cs.week.days[1];
+        let rows = ["07:00", "08:00", "09:00", "10:00", "11:00"];
+
+        let test_cases = vec![
+            RowLabelTestCase {
+                privacy: Privacy::BusyOnly,
+                exp: vec![None, None, None, None, None],
             },
-            // 14-15
-            Cell {
-                values: vec![Availability::Free.to_string()],
+            RowLabelTestCase {
+                privacy: Privacy::Titles,
+                exp: vec![
+                    None,
+                    Some("Meeting"),
+                    Some("Planning"),
+                    Some("Planning"),
+                    Some("Review"),
+                ],
             },
-            // 15-16
-            Cell {
-                values: vec![Availability::Free.to_string()],
+            RowLabelTestCase {
+                privacy: Privacy::Redacted,
+                exp: vec![
+                    None,
+                    Some("Meeting"),
+                    Some("Meeting"),
+                    Some("Meeting"),
+                    Some("Meeting"),
+                ],
             },
         ];
-        let cells = Cell::new_cells(&s);
-        assert_eq!(exp_cells.len(), cells.len());
-        assert_eq!(exp_cells, cells)
+
+        for test_case in test_cases {
+            let act: Vec<Option<String>> = rows
+                .iter()
+                .map(|r| TimeSlot::new(r.parse().unwrap(), 60))
+                .map(|ts| row_label(day, &ts, test_case.privacy))
+                .collect();
+            let exp: Vec<Option<String>> = test_case
+                .exp
+                .iter()
+                .map(|l| l.map(str::to_string))
+                .collect();
+            assert_eq!(exp, act, "{:?}", test_case.privacy);
+        }
+    }
+
+    #[test]
+    fn test_banner_stack() {
+        let banner = |first, last| Banner {
+            first,
+            last,
+            summary: String::new(),
+            availability: Availability::Busy,
+        };
+
+        let lanes = Banner::stack(vec![banner(2, 6), banner(0, 4), banner(5, 5), banner(0, 0)]);
+
+        assert_eq!(
+            vec![
+                vec![banner(0, 4), banner(5, 5)],
+                vec![banner(0, 0), banner(2, 6)],
+            ],
+            lanes
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("Offsite", truncate("Offsite", 7));
+        assert_eq!("Offs...", truncate("Offsite Berlin", 7));
+        assert_eq!("Off", truncate("Offsite", 3));
+
+        assert_eq!("Review", abbreviate("Review", 6));
+        assert_eq!("Weekl.", abbreviate("Weekly Planning", 6));
+        assert_eq!("W", abbreviate("Weekly Planning", 1));
+    }
+
+    // Compares with tests/fixtures/render/{name}.txt. Run with SNAPGENDA_UPDATE_SNAPSHOTS=1 to
+    // write the current output instead, then review the diff.
+    fn assert_snapshot(name: &str, act: &str) {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/render")
+            .join(format!("{}.txt", name));
+
+        if std::env::var_os("SNAPGENDA_UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, act).unwrap();
+            return;
+        }
+
+        let exp = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(exp, act, "snapshot {}", name);
+    }
+
+    fn ics_snapshot(file: &str, wr: WeekRequest, tz: Tz) -> CalendarSnapshot {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/ics")
+            .join(file);
+        let mut provider = crate::ics::IcsProvider::new(vec![path], tz);
+
+        CalendarSnapshot::from_provider(wr, tz, &mut provider).unwrap()
+    }
+
+    #[test]
+    fn test_render_snapshots() {
+        let week_37 = WeekRequest::new(37, 2024).unwrap();
+        let berlin = chrono_tz::Europe::Berlin;
+
+        let cs = ics_snapshot("week.ics", week_37, Tz::UTC);
+        assert_snapshot(
+            "week_full_day",
+            &render_calendar(&cs, &MatrixOptions::default()).render(),
+        );
+
+        let options = MatrixOptions {
+            hours: "07-19".parse().unwrap(),
+            row_minutes: 30,
+            ..Default::default()
+        };
+        let cs = ics_snapshot("week.ics", week_37, berlin);
+        assert_snapshot(
+            "week_office_hours",
+            &render_calendar(&cs, &options).render(),
+        );
+
+        let options = MatrixOptions {
+            hours: "08-12".parse().unwrap(),
+            row_minutes: 15,
+            ..Default::default()
+        };
+        let cs = ics_snapshot("recurring.ics", week_37, berlin);
+        assert_snapshot(
+            "recurring_mornings",
+            &render_calendar(&cs, &options).render(),
+        );
+    }
+
+    #[test]
+    fn test_render_granularity_snapshot() {
+        // Many short meetings on one day must not stretch its column.
+        let mut cs = CalendarSnapshot::new(WeekRequest::new(37, 2024).unwrap(), Tz::UTC);
+        for h in 9..17 {
+            let from = Tz::UTC.with_ymd_and_hms(2024, 9, 11, h, 0, 0).unwrap();
+            cs.add_event(event(
+                from,
+                from + TimeDelta::minutes(25),
+                Availability::Busy,
+                "Meeting",
+            ));
+        }
+        let options = MatrixOptions {
+            hours: "08-18".parse().unwrap(),
+            row_minutes: 30,
+            ..Default::default()
+        };
+        assert_snapshot("back_to_back", &render_calendar(&cs, &options).render());
+    }
+
+    #[test]
+    fn test_render_privacy_snapshots() {
+        let week_37 = WeekRequest::new(37, 2024).unwrap();
+
+        // Focus time is private and never shows its title.
+        for privacy in [Privacy::Titles, Privacy::Redacted] {
+            let options = MatrixOptions {
+                hours: "07-19".parse().unwrap(),
+                row_minutes: 30,
+                privacy,
+                ..Default::default()
+            };
+            let cs = ics_snapshot("week.ics", week_37, chrono_tz::Europe::Berlin);
+            assert_snapshot(
+                &format!("week_office_hours_{:?}", privacy).to_lowercase(),
+                &render_calendar(&cs, &options).render(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_side_by_side_snapshot() {
+        let week_37 = WeekRequest::new(37, 2024).unwrap();
+        let berlin = chrono_tz::Europe::Berlin;
+
+        // Multi-day slots stay within the columns of their calendar.
+        let calendars = vec![
+            (
+                "Jane".to_string(),
+                ics_snapshot("week.ics", week_37, berlin),
+            ),
+            (
+                "Alexander".to_string(),
+                ics_snapshot("recurring.ics", week_37, berlin),
+            ),
+        ];
+        let options = MatrixOptions {
+            hours: "08-12".parse().unwrap(),
+            row_minutes: 60,
+            privacy: Privacy::Titles,
+            ..Default::default()
+        };
+        assert_snapshot(
+            "side_by_side",
+            &render_side_by_side(&calendars, &options).render(),
+        );
+    }
+
+    #[test]
+    fn test_render_theme_snapshots() {
+        let week_37 = WeekRequest::new(37, 2024).unwrap();
+
+        // Every availability in every theme, overlaps show the one taking precedence.
+        let mut cs = CalendarSnapshot::new(week_37, Tz::UTC);
+        let at =
+            |d: u32, h: u32, min: u32| Tz::UTC.with_ymd_and_hms(2024, 9, d, h, min, 0).unwrap();
+        let events = [
+            (
+                at(9, 9, 0),
+                at(9, 11, 0),
+                Availability::Tentative,
+                "Interview",
+            ),
+            (
+                at(10, 9, 0),
+                at(10, 12, 0),
+                Availability::Focus,
+                "Focus time",
+            ),
+            (at(10, 10, 0), at(10, 11, 0), Availability::Busy, "Sync"),
+            (
+                at(11, 8, 0),
+                at(11, 13, 0),
+                Availability::OutOfOffice,
+                "Dentist",
+            ),
+            (
+                at(12, 8, 0),
+                at(12, 13, 0),
+                Availability::WorkingElsewhere,
+                "Office Hamburg",
+            ),
+            (at(12, 9, 0), at(12, 10, 0), Availability::Busy, "Planning"),
+            (at(13, 9, 0), at(13, 10, 0), Availability::Busy, "Review"),
+            (
+                at(13, 9, 30),
+                at(13, 11, 0),
+                Availability::Tentative,
+                "Lunch talk",
+            ),
+            (
+                at(14, 0, 0),
+                at(16, 0, 0),
+                Availability::OutOfOffice,
+                "Weekend trip",
+            ),
+        ];
+        for (from, to, availability, summary) in events {
+            cs.add_event(event(from, to, availability, summary));
+        }
+        for (name, theme) in [
+            ("ascii", Theme::ascii()),
+            ("unicode", Theme::unicode()),
+            ("ansi", Theme::ansi()),
+        ] {
+            let options = MatrixOptions {
+                hours: "08-13".parse().unwrap(),
+                row_minutes: 60,
+                theme,
+                ..Default::default()
+            };
+            assert_snapshot(
+                &format!("availabilities_{}", name),
+                &render_calendar(&cs, &options).render(),
+            );
+        }
+
+        for (name, theme) in [("unicode", Theme::unicode()), ("ansi", Theme::ansi())] {
+            let options = MatrixOptions {
+                hours: "07-19".parse().unwrap(),
+                row_minutes: 30,
+                theme,
+                ..Default::default()
+            };
+            let cs = ics_snapshot("week.ics", week_37, chrono_tz::Europe::Berlin);
+            assert_snapshot(
+                &format!("week_office_hours_{}", name),
+                &render_calendar(&cs, &options).render(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_banner_snapshots() {
+        // Overlapping vacations and conferences stack, long titles are cut.
+        let mut cs = CalendarSnapshot::new(WeekRequest::new(37, 2024).unwrap(), Tz::UTC);
+        let day = |d: u32| Tz::UTC.with_ymd_and_hms(2024, 9, d, 0, 0, 0).unwrap();
+        let banners = [
+            (day(9), day(14), Availability::Busy, "Vacation"),
+            (
+                day(11),
+                day(20),
+                Availability::Busy,
+                "RustConf and the unconference afterwards",
+            ),
+            (day(6), day(10), Availability::Busy, "Offsite"),
+            (
+                day(13),
+                day(14),
+                Availability::Free,
+                "Reminder: submit expenses",
+            ),
+        ];
+        for (from, to, availability, summary) in banners {
+            cs.add_event(event(from, to, availability, summary));
+        }
+        let options = MatrixOptions {
+            hours: "09-12".parse().unwrap(),
+            row_minutes: 60,
+            privacy: Privacy::Titles,
+            ..Default::default()
+        };
+        assert_snapshot("banners", &render_calendar(&cs, &options).render());
+
+        for (name, theme) in [("unicode", Theme::unicode()), ("ansi", Theme::ansi())] {
+            let options = MatrixOptions { theme, ..options };
+            assert_snapshot(
+                &format!("banners_{}", name),
+                &render_calendar(&cs, &options).render(),
+            );
+        }
     }
 }
@@ -0,0 +1,307 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+
+use crate::{http::HttpClient, token_cache::CachedToken};
+
+// https://developers.google.com/identity/protocols/oauth2/service-account#httprest
+const JWT_BEARER_GRANT: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+// Google rejects assertions valid for longer than an hour.
+const ASSERTION_LIFETIME_SECONDS: i64 = 3600;
+
+#[derive(Debug)]
+pub enum Error {
+    Read(PathBuf, String),
+    InvalidKey(PathBuf, String),
+    Sign(String),
+    /// The token endpoint refused the assertion, e.g. because domain-wide delegation is not
+    /// set up for the impersonated user.
+    Rejected(String),
+    Request(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Read(path, e) => {
+                write!(
+                    f,
+                    "Could not read service account key {}: {}",
+                    path.display(),
+                    e
+                )
+            }
+            Error::InvalidKey(path, e) => {
+                write!(f, "Invalid service account key {}: {}", path.display(), e)
+            }
+            Error::Sign(e) => write!(f, "Could not sign the service account assertion: {}", e),
+            Error::Rejected(e) => write!(f, "Service account token rejected: {}", e),
+            Error::Request(e) => write!(f, "Service account token request failed: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Error::Request(error.to_string())
+    }
+}
+
+impl std::error::Error for Error {}
+
+// The JSON key file downloaded from the Google Cloud console.
+#[derive(Deserialize)]
+struct KeyFile {
+    #[serde(rename = "type")]
+    key_type: String,
+    client_email: String,
+    private_key: String,
+    private_key_id: Option<String>,
+}
+
+/// Key of a Google service account, signs in without user interaction.
+#[derive(Clone)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    key_id: Option<String>,
+    encoding_key: EncodingKey,
+}
+
+impl ServiceAccountKey {
+    pub fn load(path: &Path) -> Result<ServiceAccountKey, Error> {
+        let content =
+            fs::read_to_string(path).map_err(|e| Error::Read(path.to_path_buf(), e.to_string()))?;
+        ServiceAccountKey::parse(&content).map_err(|e| Error::InvalidKey(path.to_path_buf(), e))
+    }
+
+    fn parse(content: &str) -> Result<ServiceAccountKey, String> {
+        let key_file: KeyFile = serde_json::from_str(content).map_err(|e| e.to_string())?;
+        // OAuth client files are JSON as well, but have no private key.
+        if key_file.key_type != "service_account" {
+            return Err(format!(
+                "expected type service_account, got {}",
+                key_file.key_type
+            ));
+        }
+        let encoding_key = EncodingKey::from_rsa_pem(key_file.private_key.as_bytes())
+            .map_err(|e| format!("private_key: {}", e))?;
+
+        Ok(ServiceAccountKey {
+            client_email: key_file.client_email,
+            key_id: key_file.private_key_id,
+            encoding_key,
+        })
+    }
+
+    /// Returns the signed JWT exchanged for an access token at token_url. With a subject the
+    /// token acts as that user, which needs domain-wide delegation.
+    fn assertion(
+        &self,
+        subject: Option<&str>,
+        scope: &str,
+        token_url: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid.clone_from(&self.key_id);
+        let claims = Claims {
+            iss: self.client_email.clone(),
+            sub: subject.map(str::to_string),
+            scope: scope.to_string(),
+            aud: token_url.to_string(),
+            iat: now.timestamp(),
+            exp: (now + TimeDelta::seconds(ASSERTION_LIFETIME_SECONDS)).timestamp(),
+        };
+
+        jsonwebtoken::encode(&header, &claims, &self.encoding_key)
+            .map_err(|e| Error::Sign(e.to_string()))
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Claims {
+    iss: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    scope: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Exchanges a signed assertion for an access token. There is no refresh token, a new
+/// assertion is signed once the token expired.
+pub fn request_token(
+    http: &HttpClient,
+    key: &ServiceAccountKey,
+    subject: Option<&str>,
+    scope: &str,
+    token_url: &str,
+) -> Result<CachedToken, Error> {
+    let now = Utc::now();
+    let assertion = key.assertion(subject, scope, token_url, now)?;
+
+    let request = http.post(token_url).form(&[
+        ("grant_type", JWT_BEARER_GRANT),
+        ("assertion", assertion.as_str()),
+    ]);
+    // Repeating the exchange only hands out another token.
+    let response = http.send_idempotent(request)?;
+    let status = response.status();
+    let body = response.text()?;
+
+    if !status.is_success() {
+        let message = match serde_json::from_str::<TokenErrorResponse>(&body) {
+            Ok(e) => match e.error_description {
+                Some(description) => format!("{}: {}", e.error, description),
+                None => e.error,
+            },
+            Err(_) => format!("{} {}", status.as_u16(), body.trim()),
+        };
+        return Err(if status.is_client_error() {
+            Error::Rejected(message)
+        } else {
+            Error::Request(message)
+        });
+    }
+
+    let token: TokenResponse =
+        serde_json::from_str(&body).map_err(|e| Error::Request(e.to_string()))?;
+
+    Ok(CachedToken {
+        access_token: token.access_token,
+        refresh_token: None,
+        expires_at: token.expires_in.map(|s| now + TimeDelta::seconds(s)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use jsonwebtoken::{DecodingKey, Validation};
+
+    use super::*;
+    use crate::http::{mock_server, MockResponse, RetryPolicy};
+
+    const KEY_FILE: &str = include_str!("../tests/fixtures/google/service_account.json");
+    const PUBLIC_KEY: &str = include_str!("../tests/fixtures/google/service_account_public.pem");
+    const SCOPE: &str = "https://www.googleapis.com/auth/calendar.readonly";
+
+    fn key() -> ServiceAccountKey {
+        ServiceAccountKey::parse(KEY_FILE).unwrap()
+    }
+
+    #[test]
+    fn test_parse_key_invalid() {
+        let test_cases = vec![
+            "not json",
+            r#"{"type": "authorized_user", "client_email": "a@b.c", "private_key": ""}"#,
+            r#"{"type": "service_account", "client_email": "a@b.c", "private_key": "nope"}"#,
+        ];
+
+        for test_case in test_cases {
+            assert!(
+                ServiceAccountKey::parse(test_case).is_err(),
+                "{}",
+                test_case
+            );
+        }
+    }
+
+    #[test]
+    fn test_assertion() {
+        let now = Utc.with_ymd_and_hms(2024, 9, 9, 8, 0, 0).unwrap();
+        let token_url = "https://oauth2.googleapis.com/token";
+
+        for subject in [None, Some("jane.doe@example.com")] {
+            let assertion = key().assertion(subject, SCOPE, token_url, now).unwrap();
+
+            let mut validation = Validation::new(Algorithm::RS256);
+            validation.set_audience(&[token_url]);
+            // The fixed time lies in the past.
+            validation.validate_exp = false;
+            let decoded = jsonwebtoken::decode::<Claims>(
+                &assertion,
+                &DecodingKey::from_rsa_pem(PUBLIC_KEY.as_bytes()).unwrap(),
+                &validation,
+            )
+            .unwrap();
+
+            assert_eq!(
+                Some("0123456789abcdef0123456789abcdef01234567"),
+                decoded.header.kid.as_deref()
+            );
+            assert_eq!(
+                Claims {
+                    iss: "dashboard@snapgenda-test.iam.gserviceaccount.com".to_string(),
+                    sub: subject.map(str::to_string),
+                    scope: SCOPE.to_string(),
+                    aud: token_url.to_string(),
+                    iat: 1725868800,
+                    exp: 1725872400,
+                },
+                decoded.claims
+            );
+        }
+    }
+
+    #[test]
+    fn test_request_token() {
+        let (base_url, server) = mock_server(vec![MockResponse::new(
+            200,
+            r#"{"access_token": "service-token", "token_type": "Bearer", "expires_in": 3599}"#,
+        )]);
+        let http = HttpClient::new(RetryPolicy::default()).unwrap();
+
+        let token =
+            request_token(&http, &key(), None, SCOPE, &format!("{}/token", base_url)).unwrap();
+
+        assert_eq!("service-token", token.access_token);
+        assert_eq!(None, token.refresh_token);
+        assert!(!token.is_expired(Utc::now()));
+        assert_eq!(vec!["POST /token HTTP/1.1"], server.join().unwrap());
+    }
+
+    #[test]
+    fn test_request_token_rejected() {
+        let (base_url, server) = mock_server(vec![MockResponse::new(
+            401,
+            r#"{"error": "unauthorized_client", "error_description": "Client is unauthorized to retrieve access tokens using this method."}"#,
+        )]);
+        let http = HttpClient::new(RetryPolicy::default()).unwrap();
+
+        let result = request_token(
+            &http,
+            &key(),
+            Some("jane.doe@example.com"),
+            SCOPE,
+            &format!("{}/token", base_url),
+        );
+        server.join().unwrap();
+
+        match result {
+            Err(Error::Rejected(e)) => assert!(e.starts_with("unauthorized_client: "), "{}", e),
+            other => panic!(
+                "expected rejection, got {:?}",
+                other.map(|t| t.access_token)
+            ),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::ffi::OsString;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use snapgenda::Availability;
+
+/// Width of a day column between its two sides.
+const FILL_WIDTH: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    /// Plain characters, safe for emails and logs.
+    Ascii,
+    /// Box-drawing characters with shaded blocks.
+    Unicode,
+    /// Availability as background color, needs a terminal.
+    Ansi,
+}
+
+/// How rows of one availability are drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateStyle {
+    /// Inside of a row cell.
+    pub fill: String,
+    /// Inside of the last row of a block, draws its bottom border.
+    pub block_end: String,
+    /// SGR parameters of the background, e.g. 41 or 48;5;202. Only used by color themes.
+    pub background: Option<String>,
+    pub banner_open: String,
+    pub banner_close: String,
+}
+
+impl StateStyle {
+    fn new(fill: &str, block_end: &str, banner: (&str, &str)) -> StateStyle {
+        StateStyle {
+            fill: fill.to_string(),
+            block_end: block_end.to_string(),
+            background: None,
+            banner_open: banner.0.to_string(),
+            banner_close: banner.1.to_string(),
+        }
+    }
+
+    fn with_background(mut self, background: &str) -> StateStyle {
+        self.background = Some(background.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub top: String,
+    pub bottom: String,
+    pub side: String,
+    pub free: StateStyle,
+    pub partial: StateStyle,
+    pub busy: StateStyle,
+    pub tentative: StateStyle,
+    pub out_of_office: StateStyle,
+    pub focus: StateStyle,
+    pub working_elsewhere: StateStyle,
+    /// Whether backgrounds are written as ANSI escape codes.
+    pub color: bool,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Theme {
+        match name {
+            ThemeName::Ascii => Theme::ascii(),
+            ThemeName::Unicode => Theme::unicode(),
+            ThemeName::Ansi => Theme::ansi(),
+        }
+    }
+
+    pub fn ascii() -> Theme {
+        Theme {
+            top: "|¯¯¯¯¯¯|".to_string(),
+            bottom: "|______|".to_string(),
+            side: "|".to_string(),
+            free: StateStyle::new(" Free ", "_Free_", ("(", ")")),
+            partial: StateStyle::new(" Part ", "_Part_", ("(", ")")),
+            busy: StateStyle::new(" Busy ", "_Busy_", ("[", "]")),
+            tentative: StateStyle::new(" Tent ", "_Tent_", ("<", ">")),
+            out_of_office: StateStyle::new(" Away ", "_Away_", ("{", "}")),
+            focus: StateStyle::new(" Focus", "_Focus", ("[", "]")),
+            working_elsewhere: StateStyle::new("Remote", "Remote", ("(", ")")),
+            color: false,
+        }
+    }
+
+    /// Blocks of different availability are told apart by their shading, so they need no
+    /// border of their own.
+    pub fn unicode() -> Theme {
+        Theme {
+            top: "┌──────┐".to_string(),
+            bottom: "└──────┘".to_string(),
+            side: "│".to_string(),
+            free: StateStyle::new("      ", "      ", ("░", "░")),
+            partial: StateStyle::new("░░▒▒░░", "░░▒▒░░", ("░", "░")),
+            busy: StateStyle::new("██████", "██████", ("▐", "▌")),
+            tentative: StateStyle::new("╱╱╱╱╱╱", "╱╱╱╱╱╱", ("╱", "╱")),
+            out_of_office: StateStyle::new("▓▓▓▓▓▓", "▓▓▓▓▓▓", ("▓", "▓")),
+            focus: StateStyle::new("▒▒▒▒▒▒", "▒▒▒▒▒▒", ("▒", "▒")),
+            working_elsewhere: StateStyle::new("┄┄┄┄┄┄", "┄┄┄┄┄┄", ("┄", "┄")),
+            color: false,
+        }
+    }
+
+    pub fn ansi() -> Theme {
+        Theme {
+            top: "┌──────┐".to_string(),
+            bottom: "└──────┘".to_string(),
+            side: "│".to_string(),
+            free: StateStyle::new("      ", "      ", (" ", " ")).with_background("42"),
+            partial: StateStyle::new(" Part ", " Part ", (" ", " ")).with_background("43"),
+            busy: StateStyle::new("      ", "      ", (" ", " ")).with_background("41"),
+            tentative: StateStyle::new(" Tent ", " Tent ", (" ", " ")).with_background("45"),
+            out_of_office: StateStyle::new(" Away ", " Away ", (" ", " ")).with_background("100"),
+            focus: StateStyle::new(" Focus", " Focus", (" ", " ")).with_background("44"),
+            working_elsewhere: StateStyle::new("Remote", "Remote", (" ", " "))
+                .with_background("46"),
+            color: true,
+        }
+    }
+
+    /// Checks that custom glyphs keep the columns aligned.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut widths = vec![
+            ("top", &self.top, FILL_WIDTH + 2),
+            ("bottom", &self.bottom, FILL_WIDTH + 2),
+            ("side", &self.side, 1),
+        ];
+        for (name, style) in [
+            ("free", &self.free),
+            ("partial", &self.partial),
+            ("busy", &self.busy),
+            ("tentative", &self.tentative),
+            ("out_of_office", &self.out_of_office),
+            ("focus", &self.focus),
+            ("working_elsewhere", &self.working_elsewhere),
+        ] {
+            widths.push((name, &style.fill, FILL_WIDTH));
+            widths.push((name, &style.block_end, FILL_WIDTH));
+            widths.push((name, &style.banner_open, 1));
+            widths.push((name, &style.banner_close, 1));
+        }
+
+        for (name, value, width) in widths {
+            if value.chars().count() != width {
+                return Err(format!(
+                    "{:?} of {} must be {} characters wide",
+                    value, name, width
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn style(&self, availability: Availability) -> &StateStyle {
+        match availability {
+            Availability::Free => &self.free,
+            Availability::Busy => &self.busy,
+            Availability::Tentative => &self.tentative,
+            Availability::OutOfOffice => &self.out_of_office,
+            Availability::Focus => &self.focus,
+            Availability::WorkingElsewhere => &self.working_elsewhere,
+        }
+    }
+
+    /// Wraps s in the background of style when the theme has colors.
+    pub fn paint(&self, style: &StateStyle, s: &str) -> String {
+        match &style.background {
+            Some(background) if self.color => format!("\x1b[{}m{}\x1b[0m", background, s),
+            _ => s.to_string(),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::ascii()
+    }
+}
+
+/// Colors are only written to terminals and never when NO_COLOR is set to a non-empty value,
+/// see https://no-color.org.
+pub fn color_enabled(is_terminal: bool, no_color: Option<OsString>) -> bool {
+    is_terminal && no_color.is_none_or(|v| v.is_empty())
+}
+
+/// Number of characters a terminal shows for s, ANSI escape codes take no space.
+pub fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+
+    for c in s.chars() {
+        if in_escape {
+            in_escape = !c.is_ascii_alphabetic();
+        } else if c == '\x1b' {
+            in_escape = true;
+        } else {
+            width += 1;
+        }
+    }
+
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_themes_are_valid() {
+        for name in ThemeName::value_variants() {
+            assert_eq!(Ok(()), Theme::new(*name).validate(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_color_enabled() {
+        assert!(color_enabled(true, None));
+        assert!(color_enabled(true, Some(OsString::new())));
+        assert!(!color_enabled(true, Some(OsString::from("1"))));
+        assert!(!color_enabled(false, None));
+    }
+
+    #[test]
+    fn test_paint() {
+        let ansi = Theme::ansi();
+        assert_eq!("\x1b[41m Busy \x1b[0m", ansi.paint(&ansi.busy, " Busy "));
+        assert_eq!(6, visible_width(&ansi.paint(&ansi.busy, " Busy ")));
+
+        let ascii = Theme::ascii();
+        assert_eq!(" Busy ", ascii.paint(&ascii.busy, " Busy "));
+    }
+}
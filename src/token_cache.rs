@@ -0,0 +1,285 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+// Tokens expiring within this window are treated as expired, so they do not run out while a
+// request is in flight.
+const EXPIRY_LEEWAY_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl CachedToken {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        match self.expires_at {
+            Some(expires_at) => now + TimeDelta::seconds(EXPIRY_LEEWAY_SECONDS) >= expires_at,
+            // Without an expiry we cannot know, the next request will tell.
+            None => false,
+        }
+    }
+}
+
+/// Stores OAuth tokens of a provider in a file only readable by the current user.
+pub struct TokenCache {
+    path: PathBuf,
+}
+
+impl TokenCache {
+    pub fn new(path: PathBuf) -> TokenCache {
+        TokenCache { path }
+    }
+
+    /// Returns the cache of the provider in the users cache directory, e.g.
+    /// ~/.cache/snapgenda/google-token.json on Linux. Characters that do not belong in a file
+    /// name, such as a / in an impersonated user, are escaped.
+    pub fn for_provider(provider: &str) -> Result<TokenCache, io::Error> {
+        Ok(TokenCache::new(cache_dir()?.join(file_name(provider))))
+    }
+
+    /// Returns every cache of the provider, including those with a suffix such as the
+    /// google-{key}-{user} caches of service accounts.
+    pub fn all_of_provider(provider: &str) -> Result<Vec<TokenCache>, io::Error> {
+        list(&cache_dir()?, provider)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns None when nothing has been cached yet.
+    pub fn load(&self) -> Result<Option<CachedToken>, io::Error> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let token = serde_json::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        Ok(Some(token))
+    }
+
+    pub fn store(&self, token: &CachedToken) -> Result<(), io::Error> {
+        if let Some(dir) = self.path.parent() {
+            create_private_dir(dir)?;
+        }
+
+        let content = serde_json::to_vec_pretty(token)?;
+
+        // Write to a temporary file first, so a crash never leaves a half written cache behind.
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = create_private_file(&tmp_path)?;
+        file.write_all(&content)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+
+    /// Removes the cached token. Returns whether there was one.
+    pub fn clear(&self) -> Result<bool, io::Error> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn cache_dir() -> Result<PathBuf, io::Error> {
+    let cache_dir = dirs::cache_dir().ok_or(io::Error::new(
+        io::ErrorKind::NotFound,
+        "could not determine cache directory",
+    ))?;
+
+    Ok(cache_dir.join("snapgenda"))
+}
+
+fn file_name(provider: &str) -> String {
+    format!("{}-token.json", escape(provider))
+}
+
+// Percent-encodes everything but the characters of an email, so different names never end up
+// in the same file.
+fn escape(name: &str) -> String {
+    let mut escaped = String::new();
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.@".contains(&b) {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    escaped
+}
+
+fn list(dir: &Path, provider: &str) -> Result<Vec<TokenCache>, io::Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let prefix = format!("{}-", escape(provider));
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_cache = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(&prefix) && n.ends_with("-token.json"));
+        if is_cache {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths.into_iter().map(TokenCache::new).collect())
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<(), io::Error> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> Result<(), io::Error> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> Result<fs::File, io::Error> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> Result<fs::File, io::Error> {
+    fs::File::create(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(expires_at: Option<DateTime<Utc>>) -> CachedToken {
+        CachedToken {
+            access_token: "ya29.access".to_string(),
+            refresh_token: Some("1//refresh".to_string()),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TokenCache::new(dir.path().join("snapgenda").join("google-token.json"));
+
+        assert_eq!(None, cache.load().unwrap());
+
+        let tkn = token(Some(Utc::now()));
+        cache.store(&tkn).unwrap();
+        assert_eq!(Some(tkn), cache.load().unwrap());
+
+        assert!(cache.clear().unwrap());
+        assert!(!cache.clear().unwrap());
+        assert_eq!(None, cache.load().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_store_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("snapgenda");
+        let cache = TokenCache::new(cache_dir.join("google-token.json"));
+        cache.store(&token(None)).unwrap();
+
+        let file_mode = fs::metadata(cache.path()).unwrap().permissions().mode();
+        assert_eq!(0o600, file_mode & 0o777);
+        let dir_mode = fs::metadata(&cache_dir).unwrap().permissions().mode();
+        assert_eq!(0o700, dir_mode & 0o777);
+    }
+
+    #[test]
+    fn test_file_name() {
+        let test_cases = vec![
+            ("google", "google-token.json"),
+            (
+                "google-sa@project.iam.gserviceaccount.com-jane.doe@example.com",
+                "google-sa@project.iam.gserviceaccount.com-jane.doe@example.com-token.json",
+            ),
+            ("google-sa-../../x", "google-sa-..%2F..%2Fx-token.json"),
+            ("google-sa-jane%2F", "google-sa-jane%252F-token.json"),
+            ("google-sa-jäne", "google-sa-j%C3%A4ne-token.json"),
+        ];
+
+        for (provider, exp) in test_cases {
+            assert_eq!(exp, file_name(provider), "{}", provider);
+        }
+    }
+
+    #[test]
+    fn test_list() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(list(&dir.path().join("snapgenda"), "google")
+            .unwrap()
+            .is_empty());
+
+        for name in [
+            "google-token.json",
+            "google-sa@example.com-jane@example.com-token.json",
+            "google-sa@example.com-jane@example.com-token.tmp",
+            "googlemail-token.json",
+            "outlook-token.json",
+        ] {
+            fs::write(dir.path().join(name), "{}").unwrap();
+        }
+
+        let names: Vec<String> = list(dir.path(), "google")
+            .unwrap()
+            .iter()
+            .map(|c| c.path().file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "google-sa@example.com-jane@example.com-token.json",
+                "google-token.json",
+            ],
+            names
+        );
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = Utc::now();
+
+        assert!(!token(None).is_expired(now));
+        assert!(!token(Some(now + TimeDelta::hours(1))).is_expired(now));
+        assert!(token(Some(now + TimeDelta::seconds(30))).is_expired(now));
+        assert!(token(Some(now - TimeDelta::hours(1))).is_expired(now));
+    }
+}
@@ -0,0 +1,376 @@
+use chrono::{
+    DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+use crate::recurrence::{Frequency, RRule};
+
+// Windows time zone names as written by Outlook and Exchange, mapped to the IANA zone of their
+// main region. https://github.com/unicode-org/cldr/blob/main/common/supplemental/windowsZones.xml
+const WINDOWS_ZONES: &[(&str, Tz)] = &[
+    ("Dateline Standard Time", Tz::Etc__GMTPlus12),
+    ("Hawaiian Standard Time", Tz::Pacific__Honolulu),
+    ("Alaskan Standard Time", Tz::America__Anchorage),
+    ("Pacific Standard Time", Tz::America__Los_Angeles),
+    ("US Mountain Standard Time", Tz::America__Phoenix),
+    ("Mountain Standard Time", Tz::America__Denver),
+    ("Central America Standard Time", Tz::America__Guatemala),
+    ("Central Standard Time", Tz::America__Chicago),
+    ("Central Standard Time (Mexico)", Tz::America__Mexico_City),
+    ("Canada Central Standard Time", Tz::America__Regina),
+    ("SA Pacific Standard Time", Tz::America__Bogota),
+    ("Eastern Standard Time", Tz::America__New_York),
+    ("US Eastern Standard Time", Tz::America__Indianapolis),
+    ("Atlantic Standard Time", Tz::America__Halifax),
+    ("SA Western Standard Time", Tz::America__La_Paz),
+    ("Pacific SA Standard Time", Tz::America__Santiago),
+    ("Newfoundland Standard Time", Tz::America__St_Johns),
+    ("E. South America Standard Time", Tz::America__Sao_Paulo),
+    ("Argentina Standard Time", Tz::America__Buenos_Aires),
+    ("UTC", Tz::UTC),
+    ("GMT Standard Time", Tz::Europe__London),
+    ("Greenwich Standard Time", Tz::Atlantic__Reykjavik),
+    ("W. Europe Standard Time", Tz::Europe__Berlin),
+    ("Central Europe Standard Time", Tz::Europe__Budapest),
+    ("Romance Standard Time", Tz::Europe__Paris),
+    ("Central European Standard Time", Tz::Europe__Warsaw),
+    ("W. Central Africa Standard Time", Tz::Africa__Lagos),
+    ("GTB Standard Time", Tz::Europe__Bucharest),
+    ("E. Europe Standard Time", Tz::Europe__Chisinau),
+    ("FLE Standard Time", Tz::Europe__Kiev),
+    ("Israel Standard Time", Tz::Asia__Jerusalem),
+    ("Egypt Standard Time", Tz::Africa__Cairo),
+    ("South Africa Standard Time", Tz::Africa__Johannesburg),
+    ("Turkey Standard Time", Tz::Europe__Istanbul),
+    ("Arab Standard Time", Tz::Asia__Riyadh),
+    ("Russian Standard Time", Tz::Europe__Moscow),
+    ("E. Africa Standard Time", Tz::Africa__Nairobi),
+    ("Iran Standard Time", Tz::Asia__Tehran),
+    ("Arabian Standard Time", Tz::Asia__Dubai),
+    ("Pakistan Standard Time", Tz::Asia__Karachi),
+    ("India Standard Time", Tz::Asia__Calcutta),
+    ("Nepal Standard Time", Tz::Asia__Katmandu),
+    ("Bangladesh Standard Time", Tz::Asia__Dhaka),
+    ("SE Asia Standard Time", Tz::Asia__Bangkok),
+    ("China Standard Time", Tz::Asia__Shanghai),
+    ("Singapore Standard Time", Tz::Asia__Singapore),
+    ("Taipei Standard Time", Tz::Asia__Taipei),
+    ("W. Australia Standard Time", Tz::Australia__Perth),
+    ("Tokyo Standard Time", Tz::Asia__Tokyo),
+    ("Korea Standard Time", Tz::Asia__Seoul),
+    ("Cen. Australia Standard Time", Tz::Australia__Adelaide),
+    ("AUS Central Standard Time", Tz::Australia__Darwin),
+    ("E. Australia Standard Time", Tz::Australia__Brisbane),
+    ("AUS Eastern Standard Time", Tz::Australia__Sydney),
+    ("Tasmania Standard Time", Tz::Australia__Hobart),
+    ("New Zealand Standard Time", Tz::Pacific__Auckland),
+];
+
+/// Time zone of a TZID parameter.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Zone {
+    Iana(Tz),
+    /// Rules of a VTIMEZONE whose TZID is no known zone name.
+    Custom(RuleZone),
+}
+
+/// Returns the IANA zone of an IANA or Windows zone name. Globally unique TZIDs start with a
+/// slash, e.g. /Europe/Berlin.
+pub fn zone_by_name(tzid: &str) -> Option<Tz> {
+    let tzid = tzid.trim().trim_start_matches('/');
+    if let Ok(tz) = tzid.parse() {
+        return Some(tz);
+    }
+
+    WINDOWS_ZONES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(tzid))
+        .map(|(_, tz)| *tz)
+}
+
+/// A STANDARD or DAYLIGHT component of a VTIMEZONE.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Observance {
+    pub daylight: bool,
+    /// Local time of the first onset.
+    pub start: NaiveDateTime,
+    /// TZOFFSETTO, the UTC offset while the observance is in effect.
+    pub offset: FixedOffset,
+    pub rrule: Option<RRule>,
+}
+
+/// Standard time, with daylight saving time between two yearly onsets. This covers the
+/// VTIMEZONEs written by current calendar apps, historic rule changes are not.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RuleZone {
+    standard: FixedOffset,
+    daylight: Option<Daylight>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Daylight {
+    offset: FixedOffset,
+    /// When daylight saving time starts, in standard time.
+    start: Onset,
+    /// When it ends, in daylight saving time.
+    end: Onset,
+}
+
+/// The nth weekday of a month, negative ordinals count from the end.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Onset {
+    month: u32,
+    ordinal: i32,
+    weekday: Weekday,
+    time: NaiveTime,
+}
+
+impl Onset {
+    fn from_observance(observance: &Observance) -> Option<Onset> {
+        let rrule = observance.rrule.as_ref()?;
+        if rrule.freq != Frequency::Yearly {
+            return None;
+        }
+        let (ordinal, weekday) = match rrule.by_day.as_slice() {
+            [(Some(ordinal), weekday)] => (*ordinal, *weekday),
+            _ => return None,
+        };
+
+        Some(Onset {
+            month: rrule
+                .by_month
+                .first()
+                .copied()
+                .unwrap_or(observance.start.month()),
+            ordinal,
+            weekday,
+            time: observance.start.time(),
+        })
+    }
+
+    fn in_year(&self, year: i32) -> Option<NaiveDateTime> {
+        let date = match self.ordinal {
+            ordinal if ordinal > 0 => {
+                // The 5th weekday means the last one in months with only four of them.
+                NaiveDate::from_weekday_of_month_opt(year, self.month, self.weekday, ordinal as u8)
+                    .or_else(|| last_weekday_of_month(year, self.month, self.weekday, 1))
+            }
+            ordinal => {
+                last_weekday_of_month(year, self.month, self.weekday, ordinal.unsigned_abs())
+            }
+        }?;
+
+        Some(date.and_time(self.time))
+    }
+}
+
+fn last_weekday_of_month(year: i32, month: u32, weekday: Weekday, nth: u32) -> Option<NaiveDate> {
+    let first_of_next = match month {
+        12 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+        _ => NaiveDate::from_ymd_opt(year, month + 1, 1),
+    }?;
+    let last = first_of_next.pred_opt()?;
+    let back = (last.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+
+    Some(last - TimeDelta::days(back as i64 + 7 * (nth as i64 - 1)))
+}
+
+impl RuleZone {
+    /// Takes the latest STANDARD and DAYLIGHT observance. Without a yearly rule for both the
+    /// latest observance applies all year. None without observances.
+    pub fn from_observances(observances: &[Observance]) -> Option<RuleZone> {
+        let latest = |daylight: bool| {
+            observances
+                .iter()
+                .filter(|o| o.daylight == daylight)
+                .max_by_key(|o| o.start)
+        };
+
+        let daylight = match (latest(false), latest(true)) {
+            (Some(standard), Some(daylight)) => {
+                match (
+                    Onset::from_observance(daylight),
+                    Onset::from_observance(standard),
+                ) {
+                    (Some(start), Some(end)) => Some(Daylight {
+                        offset: daylight.offset,
+                        start,
+                        end,
+                    }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let standard = match daylight {
+            Some(_) => latest(false)?,
+            None => observances.iter().max_by_key(|o| o.start)?,
+        };
+
+        Some(RuleZone {
+            standard: standard.offset,
+            daylight,
+        })
+    }
+
+    fn offset(self, local: NaiveDateTime) -> FixedOffset {
+        let Some(daylight) = self.daylight else {
+            return self.standard;
+        };
+        let (Some(start), Some(end)) = (
+            daylight.start.in_year(local.year()),
+            daylight.end.in_year(local.year()),
+        ) else {
+            return self.standard;
+        };
+
+        // On the southern hemisphere daylight saving time spans the turn of the year.
+        let is_daylight = if start < end {
+            start <= local && local < end
+        } else {
+            local >= start || local < end
+        };
+        if is_daylight {
+            daylight.offset
+        } else {
+            self.standard
+        }
+    }
+
+    pub fn to_utc(self, local: NaiveDateTime) -> DateTime<Utc> {
+        (local - self.offset(local)).and_utc()
+    }
+
+    pub fn to_local(self, dt: DateTime<Utc>) -> NaiveDateTime {
+        let standard = dt.naive_utc() + self.standard;
+        dt.naive_utc() + self.offset(standard)
+    }
+}
+
+/// Parses TZOFFSETTO and TZOFFSETFROM values, e.g. +0100 or -033000.
+pub fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    let (sign, digits) = match value.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if !matches!(digits.len(), 4 | 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let number = |range: std::ops::Range<usize>| digits.get(range)?.parse::<i32>().ok();
+    let seconds = number(0..2)? * 3600 + number(2..4)? * 60 + number(4..6).unwrap_or(0);
+    FixedOffset::east_opt(sign * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(m: u32, d: u32, h: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, m, d)
+            .unwrap()
+            .and_hms_opt(h, 0, 0)
+            .unwrap()
+    }
+
+    fn observance(
+        daylight: bool,
+        offset_hours: i32,
+        start: NaiveDateTime,
+        rrule: &str,
+    ) -> Observance {
+        Observance {
+            daylight,
+            start,
+            offset: FixedOffset::east_opt(offset_hours * 3600).unwrap(),
+            rrule: Some(RRule::parse(rrule).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_zone_by_name() {
+        assert_eq!(Some(Tz::Europe__Berlin), zone_by_name("Europe/Berlin"));
+        assert_eq!(Some(Tz::Europe__Berlin), zone_by_name("/Europe/Berlin"));
+        assert_eq!(
+            Some(Tz::Europe__Berlin),
+            zone_by_name("W. Europe Standard Time")
+        );
+        assert_eq!(
+            Some(Tz::America__New_York),
+            zone_by_name("eastern standard time")
+        );
+        assert_eq!(None, zone_by_name("Custom Office Time"));
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(FixedOffset::east_opt(3600), parse_offset("+0100"));
+        assert_eq!(
+            FixedOffset::east_opt(-(3 * 3600 + 1800)),
+            parse_offset("-0330")
+        );
+        assert_eq!(FixedOffset::east_opt(19800 + 15), parse_offset("+053015"));
+        assert_eq!(None, parse_offset("0100"));
+        assert_eq!(None, parse_offset("+1"));
+    }
+
+    #[test]
+    fn test_rule_zone() {
+        // Central Europe as Outlook writes it.
+        let europe = RuleZone::from_observances(&[
+            observance(
+                false,
+                1,
+                naive(1, 1, 3) - TimeDelta::days(365 * 400),
+                "FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10",
+            ),
+            observance(
+                true,
+                2,
+                naive(1, 1, 2) - TimeDelta::days(365 * 400),
+                "FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3",
+            ),
+        ])
+        .unwrap();
+        // Australia, daylight saving time spans the turn of the year.
+        let sydney = RuleZone::from_observances(&[
+            observance(false, 10, naive(4, 1, 3), "FREQ=YEARLY;BYDAY=1SU;BYMONTH=4"),
+            observance(
+                true,
+                11,
+                naive(10, 1, 2),
+                "FREQ=YEARLY;BYDAY=1SU;BYMONTH=10",
+            ),
+        ])
+        .unwrap();
+
+        let utc = |m, d, h| naive(m, d, h).and_utc();
+        // DST in Europe runs from March 31 to October 27 in 2024.
+        assert_eq!(utc(3, 30, 9), europe.to_utc(naive(3, 30, 10)));
+        assert_eq!(utc(3, 31, 8), europe.to_utc(naive(3, 31, 10)));
+        assert_eq!(utc(10, 27, 9), europe.to_utc(naive(10, 27, 10)));
+        assert_eq!(naive(7, 1, 10), europe.to_local(utc(7, 1, 8)));
+        assert_eq!(naive(12, 1, 10), europe.to_local(utc(12, 1, 9)));
+
+        // In Sydney it ends on April 7 and starts on October 6.
+        assert_eq!(
+            utc(1, 10, 0) - TimeDelta::hours(1),
+            sydney.to_utc(naive(1, 10, 10))
+        );
+        assert_eq!(utc(7, 1, 0), sydney.to_utc(naive(7, 1, 10)));
+
+        // Without yearly rules the latest offset applies all year.
+        let fixed = RuleZone::from_observances(&[Observance {
+            daylight: false,
+            start: naive(1, 1, 0),
+            offset: FixedOffset::east_opt(5 * 3600).unwrap(),
+            rrule: None,
+        }])
+        .unwrap();
+        assert_eq!(utc(7, 1, 5), fixed.to_utc(naive(7, 1, 10)));
+        assert_eq!(None, RuleZone::from_observances(&[]));
+    }
+}
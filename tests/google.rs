@@ -0,0 +1,586 @@
+//! Runs the binary against a local stand-in for the Google APIs. The token cache lives in a
+//! temporary home directory, which is only honored on Unix. Only debug builds of the binary
+//! read the endpoints from the environment.
+#![cfg(all(unix, debug_assertions))]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    process::{Command, Output},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use tempfile::TempDir;
+
+const EVENTS_LIST: &str = include_str!("fixtures/google/events_list.json");
+const FREE_BUSY: &str = include_str!("fixtures/google/free_busy.json");
+const SERVICE_ACCOUNT_KEY: &str = "tests/fixtures/google/service_account.json";
+const SERVICE_ACCOUNT_PUBLIC_KEY: &str = include_str!("fixtures/google/service_account_public.pem");
+
+#[derive(Debug, Clone)]
+struct Request {
+    method: String,
+    /// Path and query.
+    target: String,
+    authorization: Option<String>,
+    body: String,
+}
+
+impl Request {
+    fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+}
+
+struct MockGoogle {
+    base_url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockGoogle {
+    /// Answers requests by method and path, ignoring the query. Other requests get a 404. A
+    /// route given several times answers with its responses in turn, repeating the last one.
+    fn start(routes: Vec<(&'static str, &'static str, u16, &'static str)>) -> MockGoogle {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let mut responses: HashMap<(String, String), VecDeque<(u16, &'static str)>> =
+            HashMap::new();
+        for (method, path, status, body) in routes {
+            responses
+                .entry((method.to_string(), path.to_string()))
+                .or_default()
+                .push_back((status, body));
+        }
+        let received = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = read_request(&stream);
+                let key = (request.method.clone(), request.path().to_string());
+                let (status, body) = match responses.get_mut(&key) {
+                    Some(responses) if responses.len() > 1 => responses.pop_front().unwrap(),
+                    Some(responses) => responses[0],
+                    None => (404, r#"{"error": {"code": 404, "message": "Not Found"}}"#),
+                };
+                received.lock().unwrap().push(request);
+
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        MockGoogle { base_url, requests }
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn requests_to(&self, method: &str, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == method && r.path() == path)
+            .collect()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Request {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut headers: HashMap<String, String> = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        headers.insert(name.to_lowercase(), value.trim().to_string());
+    }
+
+    let length: usize = headers
+        .get("content-length")
+        .map_or(0, |l| l.parse().unwrap());
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+
+    Request {
+        method,
+        target,
+        authorization: headers.get("authorization").cloned(),
+        body: String::from_utf8(body).unwrap(),
+    }
+}
+
+/// Returns a home directory with a cached Google token. An expired token is refreshed first.
+fn home_with_token(expired: bool) -> TempDir {
+    let home = tempfile::tempdir().unwrap();
+    let expires_at = if expired {
+        "2024-01-01T00:00:00Z"
+    } else {
+        "2999-01-01T00:00:00Z"
+    };
+    let token = format!(
+        r#"{{"access_token": "cached-token", "refresh_token": "refresh-token", "expires_at": "{}"}}"#,
+        expires_at
+    );
+
+    // The cache directory of Linux and macOS.
+    for cache_dir in [".cache", "Library/Caches"] {
+        let dir = home.path().join(cache_dir).join("snapgenda");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("google-token.json"), &token).unwrap();
+    }
+
+    home
+}
+
+fn cached_access_token(home: &Path) -> String {
+    let content = fs::read_to_string(home.join(".cache/snapgenda/google-token.json"))
+        .unwrap_or_else(|_| {
+            fs::read_to_string(home.join("Library/Caches/snapgenda/google-token.json")).unwrap()
+        });
+    let token: serde_json::Value = serde_json::from_str(&content).unwrap();
+    token["access_token"].as_str().unwrap().to_string()
+}
+
+/// Lists the free slots of week 37 of 2024 in UTC, signed in with an OAuth client.
+fn snapgenda(home: &Path, mock: &MockGoogle, args: &[&str]) -> Output {
+    let mut client_args = vec![
+        "--google-id",
+        "client-id",
+        "--google-secret",
+        "client-secret",
+    ];
+    client_args.extend(args);
+    snapgenda_without_client(home, mock, &client_args)
+}
+
+fn snapgenda_without_client(home: &Path, mock: &MockGoogle, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_snapgenda"))
+        .args([
+            "--provider",
+            "google-calendar",
+            "--tz",
+            "UTC",
+            "--date",
+            "2024-09-10",
+        ])
+        .args(args)
+        .arg("free")
+        .env("HOME", home)
+        .env_remove("XDG_CACHE_HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .env(
+            "SNAPGENDA_GOOGLE_AUTH_URL",
+            format!("{}/auth", mock.base_url),
+        )
+        .env(
+            "SNAPGENDA_GOOGLE_TOKEN_URL",
+            format!("{}/token", mock.base_url),
+        )
+        .env(
+            "SNAPGENDA_GOOGLE_API_URL",
+            format!("{}/calendar/v3", mock.base_url),
+        )
+        .output()
+        .unwrap()
+}
+
+fn snapgenda_auth(home: &Path, action: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_snapgenda"))
+        .args(["auth", action])
+        .env("HOME", home)
+        .env_remove("XDG_CACHE_HOME")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_events_with_refreshed_token() {
+    let mock = MockGoogle::start(vec![
+        (
+            "POST",
+            "/token",
+            200,
+            r#"{"access_token": "fresh-token", "token_type": "Bearer", "expires_in": 3599}"#,
+        ),
+        (
+            "GET",
+            "/calendar/v3/calendars/jane.doe@example.com/events",
+            200,
+            EVENTS_LIST,
+        ),
+    ]);
+    let home = home_with_token(true);
+
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &["--calendar-email", "jane.doe@example.com"],
+    );
+
+    assert_eq!(
+        "Mon 09:00–17:00\nTue 09:00–17:00\nWed 09:00–17:00\nThu 09:00–14:30\nThu 15:15–17:00\nFri 09:00–10:00\nFri 11:00–17:00\n",
+        stdout(&output)
+    );
+
+    let token_requests = mock.requests_to("POST", "/token");
+    assert_eq!(1, token_requests.len());
+    assert!(token_requests[0]
+        .body
+        .contains("grant_type=refresh_token&refresh_token=refresh-token"));
+    assert_eq!("fresh-token", cached_access_token(home.path()));
+
+    let events_requests =
+        mock.requests_to("GET", "/calendar/v3/calendars/jane.doe@example.com/events");
+    assert_eq!(1, events_requests.len());
+    assert_eq!(
+        Some("Bearer fresh-token"),
+        events_requests[0].authorization.as_deref()
+    );
+    assert!(events_requests[0]
+        .target
+        .contains("timeMin=2024-09-09T00%3A00%3A00%2B00%3A00"));
+}
+
+#[test]
+fn test_service_account_impersonating_user() {
+    let mock = MockGoogle::start(vec![
+        (
+            "POST",
+            "/token",
+            200,
+            r#"{"access_token": "service-token", "token_type": "Bearer", "expires_in": 3599}"#,
+        ),
+        (
+            "GET",
+            "/calendar/v3/calendars/jane.doe@example.com/events",
+            200,
+            EVENTS_LIST,
+        ),
+    ]);
+    let home = tempfile::tempdir().unwrap();
+    let key = Path::new(env!("CARGO_MANIFEST_DIR")).join(SERVICE_ACCOUNT_KEY);
+    let args = [
+        "--calendar-email",
+        "jane.doe@example.com",
+        "--google-service-account-key",
+        key.to_str().unwrap(),
+        "--google-impersonate",
+        "jane.doe@example.com",
+    ];
+
+    let output = snapgenda_without_client(home.path(), &mock, &args);
+
+    assert_eq!(
+        "Mon 09:00–17:00\nTue 09:00–17:00\nWed 09:00–17:00\nThu 09:00–14:30\nThu 15:15–17:00\nFri 09:00–10:00\nFri 11:00–17:00\n",
+        stdout(&output)
+    );
+
+    let token_requests = mock.requests_to("POST", "/token");
+    assert_eq!(1, token_requests.len());
+    let form: HashMap<&str, &str> = token_requests[0]
+        .body
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+    assert_eq!(
+        Some(&"urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer"),
+        form.get("grant_type")
+    );
+
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+    validation.set_audience(&[format!("{}/token", mock.base_url)]);
+    let claims = jsonwebtoken::decode::<serde_json::Value>(
+        form["assertion"],
+        &jsonwebtoken::DecodingKey::from_rsa_pem(SERVICE_ACCOUNT_PUBLIC_KEY.as_bytes()).unwrap(),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+    assert_eq!(
+        "dashboard@snapgenda-test.iam.gserviceaccount.com",
+        claims["iss"]
+    );
+    assert_eq!("jane.doe@example.com", claims["sub"]);
+    assert_eq!(
+        "https://www.googleapis.com/auth/calendar.readonly",
+        claims["scope"]
+    );
+
+    let events_requests =
+        mock.requests_to("GET", "/calendar/v3/calendars/jane.doe@example.com/events");
+    assert_eq!(
+        Some("Bearer service-token"),
+        events_requests[0].authorization.as_deref()
+    );
+
+    // The token is cached for the next run.
+    stdout(&snapgenda_without_client(home.path(), &mock, &args));
+    assert_eq!(1, mock.requests_to("POST", "/token").len());
+
+    // The auth command covers the cache of the service account.
+    let status = stdout(&snapgenda_auth(home.path(), "status"));
+    assert!(
+        status.contains("google-dashboard@snapgenda-test.iam.gserviceaccount.com-jane.doe@example.com-token.json"),
+        "{}",
+        status
+    );
+    assert!(stdout(&snapgenda_auth(home.path(), "logout")).starts_with("Removed "));
+    assert_eq!(
+        "Not logged in.\n",
+        stdout(&snapgenda_auth(home.path(), "status"))
+    );
+}
+
+#[test]
+fn test_service_account_without_delegation() {
+    let mock = MockGoogle::start(vec![(
+        "POST",
+        "/token",
+        401,
+        r#"{"error": "unauthorized_client", "error_description": "Client is unauthorized to retrieve access tokens using this method, or client not authorized for any of the scopes requested."}"#,
+    )]);
+    let home = tempfile::tempdir().unwrap();
+    let key = Path::new(env!("CARGO_MANIFEST_DIR")).join(SERVICE_ACCOUNT_KEY);
+
+    let output = snapgenda_without_client(
+        home.path(),
+        &mock,
+        &[
+            "--calendar-email",
+            "jane.doe@example.com",
+            "--google-service-account-key",
+            key.to_str().unwrap(),
+            "--google-impersonate",
+            "jane.doe@example.com",
+        ],
+    );
+
+    assert_eq!(Some(77), output.status.code());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unauthorized_client"), "{}", stderr);
+    assert!(stderr.contains("domain-wide delegation"), "{}", stderr);
+    assert!(mock
+        .requests_to("GET", "/calendar/v3/calendars/jane.doe@example.com/events")
+        .is_empty());
+}
+
+#[test]
+fn test_forbidden_events_fall_back_to_free_busy() {
+    let mock = MockGoogle::start(vec![
+        (
+            "GET",
+            "/calendar/v3/calendars/jane.doe@example.com/events",
+            403,
+            r#"{"error": {"code": 403, "message": "Forbidden"}}"#,
+        ),
+        ("POST", "/calendar/v3/freeBusy", 200, FREE_BUSY),
+    ]);
+    let home = home_with_token(false);
+
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &["--calendar-email", "jane.doe@example.com"],
+    );
+
+    assert_eq!(
+        "Mon 09:00–17:00\nTue 09:00–17:00\nWed 09:00–12:30\nWed 14:00–17:00\nThu 09:00–17:00\nFri 09:00–17:00\n",
+        stdout(&output)
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("free/busy"));
+    // The cached token is still valid.
+    assert!(mock.requests_to("POST", "/token").is_empty());
+}
+
+#[test]
+fn test_free_busy_flag_queries_all_calendars_at_once() {
+    let mock = MockGoogle::start(vec![("POST", "/calendar/v3/freeBusy", 200, FREE_BUSY)]);
+    let home = home_with_token(false);
+
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &[
+            "--calendar-email",
+            "jane.doe@example.com,john.roe@example.com",
+            "--free-busy",
+        ],
+    );
+
+    assert_eq!(
+        "Mon 09:00–17:00\nTue 09:00–17:00\nWed 09:00–12:30\nWed 14:00–17:00\nThu 09:00–17:00\nFri 09:00–17:00\n",
+        stdout(&output)
+    );
+
+    let requests = mock.requests();
+    assert_eq!(1, requests.len(), "{:?}", requests);
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(
+        serde_json::json!([{"id": "jane.doe@example.com"}, {"id": "john.roe@example.com"}]),
+        body["items"]
+    );
+}
+
+#[test]
+fn test_server_error() {
+    let mock = MockGoogle::start(vec![(
+        "GET",
+        "/calendar/v3/calendars/jane.doe@example.com/events",
+        500,
+        r#"{"error": {"code": 500, "message": "Backend Error"}}"#,
+    )]);
+    let home = home_with_token(false);
+
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &[
+            "--calendar-email",
+            "jane.doe@example.com",
+            "--max-retries",
+            "1",
+        ],
+    );
+
+    assert_eq!(Some(69), output.status.code());
+    // Retried once before giving up.
+    assert_eq!(
+        2,
+        mock.requests_to("GET", "/calendar/v3/calendars/jane.doe@example.com/events")
+            .len()
+    );
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Error: Google answered 500: Backend Error\nHint: "),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_retries_unavailable_and_rate_limited_requests() {
+    let events_path = "/calendar/v3/calendars/jane.doe@example.com/events";
+    let mock = MockGoogle::start(vec![
+        (
+            "GET",
+            events_path,
+            503,
+            r#"{"error": {"code": 503, "message": "Backend Error"}}"#,
+        ),
+        ("GET", events_path, 200, EVENTS_LIST),
+        (
+            "POST",
+            "/calendar/v3/freeBusy",
+            429,
+            r#"{"error": {"code": 429, "message": "Rate Limit Exceeded"}}"#,
+        ),
+        ("POST", "/calendar/v3/freeBusy", 200, FREE_BUSY),
+    ]);
+    let home = home_with_token(false);
+
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &[
+            "--calendar-email",
+            "jane.doe@example.com",
+            "--max-retries",
+            "1",
+        ],
+    );
+    stdout(&output);
+    assert_eq!(2, mock.requests_to("GET", events_path).len());
+
+    // freeBusy.query is a POST that only reads, so it is retried as well.
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &[
+            "--calendar-email",
+            "jane.doe@example.com",
+            "--free-busy",
+            "--max-retries",
+            "1",
+        ],
+    );
+    assert_eq!(
+        "Mon 09:00–17:00\nTue 09:00–17:00\nWed 09:00–12:30\nWed 14:00–17:00\nThu 09:00–17:00\nFri 09:00–17:00\n",
+        stdout(&output)
+    );
+    assert_eq!(2, mock.requests_to("POST", "/calendar/v3/freeBusy").len());
+}
+
+#[test]
+fn test_rate_limited_without_retries() {
+    let mock = MockGoogle::start(vec![(
+        "GET",
+        "/calendar/v3/calendars/jane.doe@example.com/events",
+        429,
+        r#"{"error": {"code": 429, "message": "Rate Limit Exceeded"}}"#,
+    )]);
+    let home = home_with_token(false);
+
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &[
+            "--calendar-email",
+            "jane.doe@example.com",
+            "--max-retries",
+            "0",
+        ],
+    );
+
+    assert_eq!(Some(75), output.status.code());
+    assert_eq!(1, mock.requests().len());
+}
+
+#[test]
+fn test_calendar_not_found() {
+    let mock = MockGoogle::start(vec![]);
+    let home = home_with_token(false);
+
+    let output = snapgenda(
+        home.path(),
+        &mock,
+        &["--calendar-email", "typo@example.com"],
+    );
+
+    assert_eq!(Some(66), output.status.code());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Calendar typo@example.com not found"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--calendar-email"), "{}", stderr);
+}